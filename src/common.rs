@@ -14,3 +14,48 @@ pub fn read_file_to_string(path: &str) -> String {
 
     return result_string;
 }
+
+pub const fn parse_uint(bytes: &[u8], mut index: usize) -> (u32, usize) {
+    let mut value = 0u32;
+
+    while index < bytes.len() && bytes[index].is_ascii_digit() {
+        value = value * 10 + (bytes[index] - b'0') as u32;
+        index += 1;
+    }
+
+    (value, index)
+}
+
+pub const fn parse_int(bytes: &[u8], index: usize) -> (i32, usize) {
+    if index < bytes.len() && bytes[index] == b'-' {
+        let (value, index) = parse_uint(bytes, index + 1);
+        (-(value as i32), index)
+    } else {
+        let (value, index) = parse_uint(bytes, index);
+        (value as i32, index)
+    }
+}
+
+pub const fn starts_with(bytes: &[u8], index: usize, prefix: &[u8]) -> bool {
+    if index + prefix.len() > bytes.len() {
+        return false;
+    }
+
+    let mut i = 0;
+    while i < prefix.len() {
+        if bytes[index + i] != prefix[i] {
+            return false;
+        }
+        i += 1;
+    }
+
+    true
+}
+
+pub const fn next_line(bytes: &[u8], mut index: usize) -> usize {
+    while index < bytes.len() && bytes[index] != b'\n' {
+        index += 1;
+    }
+
+    index + 1
+}
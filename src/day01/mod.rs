@@ -1,4 +1,7 @@
-use crate::common::read_file_to_string;
+use crate::common::{next_line, parse_uint, read_file_to_string};
+
+#[cfg(feature = "embedded-inputs")]
+pub const EMBEDDED: (u32, u32) = solve_const(include_str!("./input"));
 
 pub fn solve() -> (u32, u32) {
     let result_string = read_file_to_string("src/day01/input");
@@ -14,6 +17,42 @@ pub fn solve() -> (u32, u32) {
     return (groups[0], groups[0] + groups[1] + groups[2]);
 }
 
+const fn insert_top(top: &mut [u32; 3], group: u32) {
+    let mut value = group;
+    let mut i = 0;
+
+    while i < top.len() {
+        if value > top[i] {
+            let tmp = top[i];
+            top[i] = value;
+            value = tmp;
+        }
+        i += 1;
+    }
+}
+
+pub const fn solve_const(input: &str) -> (u32, u32) {
+    let bytes = input.as_bytes();
+    let mut top = [0u32; 3];
+    let mut group = 0u32;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] == b'\n' {
+            insert_top(&mut top, group);
+            group = 0;
+            i += 1;
+        } else {
+            let (value, end) = parse_uint(bytes, i);
+            group += value;
+            i = next_line(bytes, end);
+        }
+    }
+    insert_top(&mut top, group);
+
+    (top[0], top[0] + top[1] + top[2])
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -25,4 +64,17 @@ mod tests {
         assert_eq!(p1, 72478);
         assert_eq!(p2, 210367);
     }
+
+    #[test]
+    fn it_works_const() {
+        let input = read_file_to_string("src/day01/input");
+
+        assert_eq!(solve_const(&input), solve());
+    }
+
+    #[cfg(feature = "embedded-inputs")]
+    #[test]
+    fn embedded_matches_runtime() {
+        assert_eq!(EMBEDDED, solve());
+    }
 }
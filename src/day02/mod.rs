@@ -1,3 +1,8 @@
+use crate::common::next_line;
+
+#[cfg(feature = "embedded-inputs")]
+pub const EMBEDDED: (u32, u32) = solve_const(include_str!("./input"));
+
 pub fn solve(lines: &[String]) -> (u32, u32) {
     let score1 = lines.iter().fold(0, |acc, line| {
         acc + match line.as_str() {
@@ -32,6 +37,33 @@ pub fn solve(lines: &[String]) -> (u32, u32) {
     (score1, score2)
 }
 
+pub const fn solve_const(input: &str) -> (u32, u32) {
+    let bytes = input.as_bytes();
+    let (mut score1, mut score2) = (0u32, 0u32);
+    let mut i = 0;
+
+    while i + 2 < bytes.len() {
+        let (them, us) = (bytes[i], bytes[i + 2]);
+
+        // Anything that isn't a round, like a blank line, is skipped
+        if matches!(them, b'A'..=b'C') && matches!(us, b'X'..=b'Z') {
+            let opponent = (them - b'A') as u32;
+            let column = (us - b'X') as u32;
+
+            // 0 = lose, 1 = draw, 2 = win
+            let outcome = (column + 4 - opponent) % 3;
+            score1 += column + 1 + outcome * 3;
+
+            let shape = (opponent + column + 2) % 3;
+            score2 += shape + 1 + column * 3;
+        }
+
+        i = next_line(bytes, i);
+    }
+
+    (score1, score2)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -56,4 +88,22 @@ mod tests {
         assert_eq!(p1, 13484);
         assert_eq!(p2, 13433);
     }
+
+    #[test]
+    fn it_works_const() {
+        let lines = map_lines_to_strings("src/day02/input");
+
+        assert_eq!(solve_const(include_str!("./input")), solve(&lines));
+        assert_eq!(solve_const("A Y\nB X\nC Z").0, 15);
+        assert_eq!(solve_const("A Y\r\n\r\nB X\r\nC Z\r\n"), (15, 12));
+        assert_eq!(solve_const("\nA Y\n\n\nB X\nC Z\n\n"), (15, 12));
+    }
+
+    #[cfg(feature = "embedded-inputs")]
+    #[test]
+    fn embedded_matches_runtime() {
+        let lines = map_lines_to_strings("src/day02/input");
+
+        assert_eq!(EMBEDDED, solve(&lines));
+    }
 }
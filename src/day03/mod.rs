@@ -1,4 +1,103 @@
-use std::collections::HashSet;
+use std::collections::{BTreeSet, HashSet};
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct SharedItem {
+    pub item: char,
+    pub priority: Option<u32>,
+    pub positions: Vec<Vec<usize>>,
+}
+
+#[derive(Debug)]
+pub struct RucksackReport {
+    pub index: usize,
+    pub shared: Vec<SharedItem>,
+}
+
+#[derive(Debug)]
+pub struct GroupReport {
+    pub index: usize,
+    pub shared: Vec<SharedItem>,
+}
+
+#[derive(Debug)]
+pub struct Report {
+    pub rucksacks: Vec<RucksackReport>,
+    pub groups: Vec<GroupReport>,
+}
+
+impl Report {
+    pub fn invalid_rucksacks(&self) -> impl Iterator<Item = &RucksackReport> {
+        self.rucksacks.iter().filter(|r| r.shared.len() != 1)
+    }
+
+    pub fn invalid_groups(&self) -> impl Iterator<Item = &GroupReport> {
+        self.groups.iter().filter(|g| g.shared.len() != 1)
+    }
+}
+
+fn priority(c: char) -> Option<u32> {
+    if c.is_ascii_lowercase() {
+        Some(1 + u32::from(c) - u32::from('a'))
+    } else if c.is_ascii_uppercase() {
+        Some(27 + u32::from(c) - u32::from('A'))
+    } else {
+        None
+    }
+}
+
+fn shared_items(parts: &[&str]) -> Vec<SharedItem> {
+    let mut common: BTreeSet<char> = parts[0].chars().collect();
+    for part in &parts[1..] {
+        let set: BTreeSet<char> = part.chars().collect();
+        common = &common & &set;
+    }
+
+    common
+        .into_iter()
+        .map(|item| SharedItem {
+            item,
+            priority: priority(item),
+            positions: parts
+                .iter()
+                .map(|part| {
+                    part.chars()
+                        .enumerate()
+                        .filter(|&(_, c)| c == item)
+                        .map(|(i, _)| i)
+                        .collect()
+                })
+                .collect(),
+        })
+        .collect()
+}
+
+pub fn report(lines: &[String]) -> Report {
+    let rucksacks = lines
+        .iter()
+        .enumerate()
+        .map(|(index, line)| {
+            let (first, second) = line.split_at(line.len() / 2);
+            RucksackReport {
+                index,
+                shared: shared_items(&[first, second]),
+            }
+        })
+        .collect();
+
+    let groups = lines
+        .chunks(3)
+        .enumerate()
+        .map(|(index, group)| {
+            let parts: Vec<&str> = group.iter().map(|l| l.as_str()).collect();
+            GroupReport {
+                index,
+                shared: shared_items(&parts),
+            }
+        })
+        .collect();
+
+    Report { rucksacks, groups }
+}
 
 pub fn solve(lines: &[String]) -> u32 {
     let mut score = 0u32;
@@ -19,10 +118,8 @@ pub fn solve(lines: &[String]) -> u32 {
         assert_eq!(i.len(), 1);
         let c = *i[0];
 
-        if c.is_ascii_lowercase() {
-            score += 1 + u32::from(c) - u32::from('a');
-        } else if c.is_ascii_uppercase() {
-            score += 27 + u32::from(c) - u32::from('A');
+        if let Some(p) = priority(c) {
+            score += p;
         } else {
             println!("c={}", c);
             panic!("Not ascii?");
@@ -57,10 +154,8 @@ pub fn solve_2(lines: &[String]) -> u32 {
         assert_eq!(i.len(), 1);
         let c = *i[0];
 
-        if c.is_ascii_lowercase() {
-            score += 1 + u32::from(c) - u32::from('a');
-        } else if c.is_ascii_uppercase() {
-            score += 27 + u32::from(c) - u32::from('A');
+        if let Some(p) = priority(c) {
+            score += p;
         } else {
             println!("c={}", c);
             panic!("Not ascii?");
@@ -122,4 +217,51 @@ mod tests {
 
         assert_eq!(p1, 2581);
     }
+
+    #[test]
+    fn report_simple() {
+        let vec = vec![
+            String::from("vJrwpWtwJgWrhcsFMMfFFhFp"),
+            String::from("jqHRNqRjqzjGDLGLrsFMfFZSrLrFZsSL"),
+            String::from("PmmdzqPrVvPwwTWBwg"),
+            String::from("wMqvLMZHhHMvwLHjbvcjnnSBnvTQFn"),
+            String::from("ttgJtRGJQctTZtZT"),
+            String::from("CrZsJsPPZsGzwwsLwLmpwMDw"),
+        ];
+
+        let report = report(&vec);
+
+        assert_eq!(
+            report.rucksacks[0].shared,
+            vec![SharedItem {
+                item: 'p',
+                priority: Some(16),
+                positions: vec![vec![4], vec![11]],
+            }]
+        );
+        assert_eq!(report.groups[1].shared[0].item, 'Z');
+        assert_eq!(report.invalid_rucksacks().count(), 0);
+        assert_eq!(report.invalid_groups().count(), 0);
+    }
+
+    #[test]
+    fn report_flags_multiple_shared_items() {
+        let vec = vec![
+            String::from("abAB"),
+            String::from("abab"),
+            String::from("aXbY"),
+        ];
+
+        let report = report(&vec);
+
+        let invalid: Vec<_> = report.invalid_rucksacks().map(|r| r.index).collect();
+        assert_eq!(invalid, vec![0, 1, 2]);
+        assert!(report.rucksacks[2].shared.is_empty());
+        assert_eq!(report.rucksacks[1].shared.len(), 2);
+        assert_eq!(report.groups[0].shared.len(), 2);
+        assert_eq!(
+            report.groups[0].shared[1].positions,
+            vec![vec![1], vec![1, 3], vec![2]]
+        );
+    }
 }
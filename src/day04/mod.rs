@@ -1,24 +1,367 @@
-use std::ops::Range;
+use std::cmp::Reverse;
+use std::collections::BTreeSet;
+use std::ops::RangeInclusive;
+use std::str::FromStr;
 
-trait ContainsOverlapsRange<Idx> {
-    fn contains_range(&self, item: &Range<Idx>) -> bool;
-    fn overlaps_range(&self, item: &Range<Idx>) -> bool;
+use crate::common::{next_line, parse_uint};
+
+#[cfg(feature = "embedded-inputs")]
+pub const EMBEDDED: (u32, u32) = solve_const(include_str!("./input"));
+
+pub trait ContainsOverlapsRange<Rhs = Self> {
+    fn contains_range(&self, item: &Rhs) -> bool;
+    fn overlaps_range(&self, item: &Rhs) -> bool;
 }
 
-impl<Idx> ContainsOverlapsRange<Idx> for Range<Idx>
+impl<Idx> ContainsOverlapsRange for RangeInclusive<Idx>
 where
     Idx: Ord,
 {
-    fn contains_range(&self, item: &Range<Idx>) -> bool {
-        self.start <= item.start && self.end >= item.end
-            || item.start <= self.start && item.end >= self.end
+    fn contains_range(&self, item: &RangeInclusive<Idx>) -> bool {
+        self.start() <= item.start() && item.end() <= self.end()
+    }
+
+    fn overlaps_range(&self, item: &RangeInclusive<Idx>) -> bool {
+        self.start() <= item.end() && item.start() <= self.end()
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Interval {
+    pub start: u32,
+    pub end: u32,
+}
+
+impl Interval {
+    pub fn new(start: u32, end: u32) -> Self {
+        assert!(
+            start <= end,
+            "Interval start {} is after end {}",
+            start,
+            end
+        );
+        Interval { start, end }
+    }
+
+    pub fn len(&self) -> u64 {
+        (self.end - self.start) as u64 + 1
+    }
+
+    pub fn contains(&self, section: u32) -> bool {
+        self.start <= section && section <= self.end
+    }
+
+    pub fn intersection(&self, other: &Interval) -> Option<Interval> {
+        if self.overlaps_range(other) {
+            Some(Interval::new(
+                self.start.max(other.start),
+                self.end.min(other.end),
+            ))
+        } else {
+            None
+        }
+    }
+
+    pub fn union(&self, other: &Interval) -> IntervalSet {
+        IntervalSet::from_iter([*self, *other])
+    }
+
+    pub fn difference(&self, other: &Interval) -> IntervalSet {
+        let mut intervals = vec![];
+
+        if !self.overlaps_range(other) {
+            intervals.push(*self);
+        } else {
+            if self.start < other.start {
+                intervals.push(Interval::new(self.start, other.start - 1));
+            }
+            if other.end < self.end {
+                intervals.push(Interval::new(other.end + 1, self.end));
+            }
+        }
+
+        IntervalSet { intervals }
+    }
+}
+
+impl ContainsOverlapsRange for Interval {
+    fn contains_range(&self, item: &Interval) -> bool {
+        (self.start..=self.end).contains_range(&(item.start..=item.end))
+    }
+
+    fn overlaps_range(&self, item: &Interval) -> bool {
+        (self.start..=self.end).overlaps_range(&(item.start..=item.end))
+    }
+}
+
+impl From<RangeInclusive<u32>> for Interval {
+    fn from(range: RangeInclusive<u32>) -> Self {
+        Interval::new(*range.start(), *range.end())
+    }
+}
+
+impl FromStr for Interval {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (start, end) = s
+            .split_once('-')
+            .ok_or_else(|| format!("Missing '-' in section range {:?}", s))?;
+        let start: u32 = start.parse().map_err(|e| format!("{:?}: {}", s, e))?;
+        let end: u32 = end.parse().map_err(|e| format!("{:?}: {}", s, e))?;
+
+        if start > end {
+            return Err(format!("Section range {:?} ends before it starts", s));
+        }
+
+        Ok(Interval::new(start, end))
+    }
+}
+
+// Sorted, disjoint and non-adjacent intervals
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct IntervalSet {
+    intervals: Vec<Interval>,
+}
+
+impl IntervalSet {
+    pub fn intervals(&self) -> &[Interval] {
+        &self.intervals
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.intervals.is_empty()
     }
 
-    fn overlaps_range(&self, item: &Range<Idx>) -> bool {
-        self.start <= item.start && self.end >= item.start
-            || self.start <= item.end && self.end >= item.end
-            || item.start <= self.start && item.end >= self.start
-            || item.start <= self.end && item.end >= self.end
+    pub fn len(&self) -> u64 {
+        self.intervals.iter().map(|i| i.len()).sum()
+    }
+
+    pub fn contains(&self, section: u32) -> bool {
+        let idx = self.intervals.partition_point(|i| i.end < section);
+        self.intervals.get(idx).is_some_and(|i| i.contains(section))
+    }
+
+    pub fn union(&self, other: &IntervalSet) -> IntervalSet {
+        self.intervals
+            .iter()
+            .chain(other.intervals.iter())
+            .copied()
+            .collect()
+    }
+
+    pub fn intersection(&self, other: &IntervalSet) -> IntervalSet {
+        let mut intervals = vec![];
+        let (mut i, mut j) = (0, 0);
+
+        while i < self.intervals.len() && j < other.intervals.len() {
+            let (a, b) = (&self.intervals[i], &other.intervals[j]);
+
+            if let Some(common) = a.intersection(b) {
+                intervals.push(common);
+            }
+
+            if a.end < b.end {
+                i += 1;
+            } else {
+                j += 1;
+            }
+        }
+
+        IntervalSet { intervals }
+    }
+
+    pub fn difference(&self, other: &IntervalSet) -> IntervalSet {
+        let mut intervals = vec![];
+        let mut j = 0;
+
+        for &a in &self.intervals {
+            let mut rest = Some(a);
+
+            while let Some(current) = rest {
+                while j < other.intervals.len() && other.intervals[j].end < current.start {
+                    j += 1;
+                }
+
+                match other.intervals.get(j) {
+                    Some(b) if b.overlaps_range(&current) => {
+                        if current.start < b.start {
+                            intervals.push(Interval::new(current.start, b.start - 1));
+                        }
+                        rest = if b.end < current.end {
+                            Some(Interval::new(b.end + 1, current.end))
+                        } else {
+                            None
+                        };
+                    }
+                    _ => {
+                        intervals.push(current);
+                        rest = None;
+                    }
+                }
+            }
+        }
+
+        IntervalSet { intervals }
+    }
+}
+
+impl FromIterator<Interval> for IntervalSet {
+    fn from_iter<T: IntoIterator<Item = Interval>>(iter: T) -> Self {
+        let mut sorted: Vec<_> = iter.into_iter().collect();
+        sorted.sort();
+
+        let mut intervals: Vec<Interval> = Vec::with_capacity(sorted.len());
+
+        for interval in sorted {
+            match intervals.last_mut() {
+                Some(last) if interval.start as u64 <= last.end as u64 + 1 => {
+                    last.end = last.end.max(interval.end);
+                }
+                _ => intervals.push(interval),
+            }
+        }
+
+        IntervalSet { intervals }
+    }
+}
+
+// Splits the sections into maximal runs covered by the same number of intervals,
+// skipping runs not covered at all
+pub fn coverage(intervals: &[Interval]) -> Vec<(Interval, usize)> {
+    let mut events: Vec<(u64, isize)> = intervals
+        .iter()
+        .flat_map(|i| [(i.start as u64, 1), (i.end as u64 + 1, -1)])
+        .collect();
+    events.sort();
+
+    let mut result = vec![];
+    let mut count = 0isize;
+    let mut idx = 0;
+
+    while idx < events.len() {
+        let pos = events[idx].0;
+
+        while idx < events.len() && events[idx].0 == pos {
+            count += events[idx].1;
+            idx += 1;
+        }
+
+        if count > 0 {
+            let next = events[idx].0;
+            result.push((Interval::new(pos as u32, (next - 1) as u32), count as usize));
+        }
+    }
+
+    result
+}
+
+pub fn covered_at_least(intervals: &[Interval], times: usize) -> IntervalSet {
+    coverage(intervals)
+        .into_iter()
+        .filter(|&(_, count)| count >= times)
+        .map(|(interval, _)| interval)
+        .collect()
+}
+
+fn parse_pair(line: &str) -> (Interval, Interval) {
+    let (first, second) = line.split_once(',').unwrap();
+
+    (first.parse().unwrap(), second.parse().unwrap())
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ElfRelations {
+    pub contained_by: Vec<usize>,
+    pub overlaps: Vec<usize>,
+}
+
+#[derive(Debug)]
+pub struct RosterAnalysis {
+    pub relations: Vec<ElfRelations>,
+    pub largest_clique: Vec<usize>,
+    pub clique_sections: Option<Interval>,
+    pub gaps: IntervalSet,
+}
+
+// Every assignment in the file, elf `2 * line + k` being the k:th elf on a line
+pub fn parse_roster(lines: &[String]) -> Vec<Interval> {
+    lines
+        .iter()
+        .flat_map(|line| {
+            let (first, second) = parse_pair(line);
+            [first, second]
+        })
+        .collect()
+}
+
+pub fn analyze_roster(assignments: &[Interval]) -> RosterAnalysis {
+    let mut relations: Vec<ElfRelations> = assignments
+        .iter()
+        .map(|_| ElfRelations::default())
+        .collect();
+
+    let mut order: Vec<usize> = (0..assignments.len()).collect();
+    order.sort_by_key(|&i| (assignments[i].start, Reverse(assignments[i].end)));
+
+    // Assignments that started before the current one, keyed by where they end
+    let mut active: BTreeSet<(u32, usize)> = BTreeSet::new();
+
+    for &i in &order {
+        let current = assignments[i];
+
+        while let Some(&(end, j)) = active.first() {
+            if end >= current.start {
+                break;
+            }
+            active.remove(&(end, j));
+        }
+
+        for &(end, j) in &active {
+            relations[i].overlaps.push(j);
+            relations[j].overlaps.push(i);
+
+            if end >= current.end {
+                relations[i].contained_by.push(j);
+            }
+            if assignments[j].start == current.start && end == current.end {
+                relations[j].contained_by.push(i);
+            }
+        }
+
+        active.insert((current.end, i));
+    }
+
+    for relation in relations.iter_mut() {
+        relation.overlaps.sort();
+        relation.contained_by.sort();
+    }
+
+    let clique_sections = coverage(assignments)
+        .into_iter()
+        .max_by_key(|&(interval, count)| (count, Reverse(interval.start)))
+        .map(|(interval, _)| interval);
+
+    let largest_clique = clique_sections.map_or(vec![], |sections| {
+        (0..assignments.len())
+            .filter(|&i| assignments[i].contains_range(&sections))
+            .collect()
+    });
+
+    let gaps = match (
+        assignments.iter().map(|i| i.start).min(),
+        assignments.iter().map(|i| i.end).max(),
+    ) {
+        (Some(start), Some(end)) => IntervalSet::from_iter([Interval::new(start, end)])
+            .difference(&assignments.iter().copied().collect()),
+        _ => IntervalSet::default(),
+    };
+
+    RosterAnalysis {
+        relations,
+        largest_clique,
+        clique_sections,
+        gaps,
     }
 }
 
@@ -26,26 +369,13 @@ fn solve(lines: &Vec<String>) -> (u32, u32) {
     let mut part_1_score = 0u32;
     let mut part_2_score = 0u32;
     for line in lines {
-        let pairs: Vec<_> = line
-            .split(',')
-            .map(|range| {
-                let vec: Vec<_> = range
-                    .split('-')
-                    .map(|n| n.parse::<u32>().unwrap())
-                    .collect();
-
-                vec[0]..vec[1]
-            })
-            .collect();
-
-        let range1 = &pairs[0];
-        let range2 = &pairs[1];
+        let (range1, range2) = parse_pair(line);
 
-        if range1.contains_range(range2) {
+        if range1.contains_range(&range2) || range2.contains_range(&range1) {
             part_1_score += 1
         }
 
-        if range1.overlaps_range(range2) {
+        if range1.overlaps_range(&range2) {
             part_2_score += 1
         }
     }
@@ -53,6 +383,36 @@ fn solve(lines: &Vec<String>) -> (u32, u32) {
     return (part_1_score, part_2_score);
 }
 
+pub const fn solve_const(input: &str) -> (u32, u32) {
+    let bytes = input.as_bytes();
+    let (mut part_1_score, mut part_2_score) = (0u32, 0u32);
+    let mut i = 0;
+
+    while i < bytes.len() {
+        // Anything that isn't a pair, like a blank line, is skipped
+        if !bytes[i].is_ascii_digit() {
+            i = next_line(bytes, i);
+            continue;
+        }
+
+        let (a, end) = parse_uint(bytes, i);
+        let (b, end) = parse_uint(bytes, end + 1);
+        let (c, end) = parse_uint(bytes, end + 1);
+        let (d, end) = parse_uint(bytes, end + 1);
+
+        if a <= c && d <= b || c <= a && b <= d {
+            part_1_score += 1;
+        }
+        if a <= d && c <= b {
+            part_2_score += 1;
+        }
+
+        i = next_line(bytes, end);
+    }
+
+    (part_1_score, part_2_score)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -81,4 +441,164 @@ mod tests {
 
         assert_eq!(res, (466, 865));
     }
+
+    #[test]
+    fn interval_algebra() {
+        let a = Interval::from(2..=6);
+        let b = Interval::from(4..=8);
+
+        assert_eq!(a.len(), 5);
+        assert_eq!(a.intersection(&b), Some(Interval::new(4, 6)));
+        assert_eq!(a.union(&b).intervals(), &[Interval::new(2, 8)]);
+        assert_eq!(a.difference(&b).intervals(), &[Interval::new(2, 3)]);
+        assert_eq!(
+            Interval::new(1, 9).difference(&a).intervals(),
+            &[Interval::new(1, 1), Interval::new(7, 9)]
+        );
+        assert_eq!(Interval::new(1, 1).intersection(&a), None);
+        assert!("6-6".parse::<Interval>().unwrap().contains(6));
+        assert!("6-4".parse::<Interval>().is_err());
+    }
+
+    #[test]
+    fn interval_set_merges_and_combines() {
+        let set: IntervalSet = [(7..=9), (1..=2), (3..=4), (12..=12), (8..=10)]
+            .into_iter()
+            .map(Interval::from)
+            .collect();
+
+        assert_eq!(
+            set.intervals(),
+            &[
+                Interval::new(1, 4),
+                Interval::new(7, 10),
+                Interval::new(12, 12)
+            ]
+        );
+        assert_eq!(set.len(), 9);
+        assert!(set.contains(12));
+        assert!(!set.contains(11));
+
+        let other: IntervalSet = [Interval::new(3, 8), Interval::new(10, 20)]
+            .into_iter()
+            .collect();
+
+        assert_eq!(
+            set.intersection(&other).intervals(),
+            &[
+                Interval::new(3, 4),
+                Interval::new(7, 8),
+                Interval::new(10, 10),
+                Interval::new(12, 12)
+            ]
+        );
+        assert_eq!(
+            set.difference(&other).intervals(),
+            &[Interval::new(1, 2), Interval::new(9, 9)]
+        );
+        assert_eq!(set.union(&other).intervals(), &[Interval::new(1, 20)]);
+    }
+
+    #[test]
+    fn coverage_counts_elves() {
+        let intervals = [
+            Interval::new(2, 4),
+            Interval::new(3, 7),
+            Interval::new(6, 6),
+        ];
+
+        assert_eq!(
+            coverage(&intervals),
+            vec![
+                (Interval::new(2, 2), 1),
+                (Interval::new(3, 4), 2),
+                (Interval::new(5, 5), 1),
+                (Interval::new(6, 6), 2),
+                (Interval::new(7, 7), 1),
+            ]
+        );
+        assert_eq!(
+            covered_at_least(&intervals, 2).intervals(),
+            &[Interval::new(3, 4), Interval::new(6, 6)]
+        );
+    }
+
+    #[test]
+    fn roster_analysis_simple() {
+        let vec = vec![
+            String::from("2-4,6-8"),
+            String::from("2-3,4-5"),
+            String::from("5-7,7-9"),
+            String::from("2-8,3-7"),
+            String::from("6-6,4-6"),
+            String::from("2-6,4-8"),
+            String::from("12-13,13-13"),
+        ];
+
+        let roster = parse_roster(&vec);
+        let analysis = analyze_roster(&roster);
+
+        assert_eq!(
+            analysis.relations[8].contained_by,
+            vec![1, 4, 6, 7, 9, 10, 11]
+        );
+        assert_eq!(analysis.relations[3].contained_by, vec![6, 7, 9, 10, 11]);
+        assert_eq!(analysis.relations[0].overlaps, vec![2, 3, 6, 7, 9, 10, 11]);
+        assert_eq!(analysis.relations[12].contained_by, vec![]);
+        assert_eq!(analysis.relations[13].contained_by, vec![12]);
+        assert_eq!(analysis.clique_sections, Some(Interval::new(6, 6)));
+        assert_eq!(analysis.largest_clique, vec![1, 4, 6, 7, 8, 9, 10, 11]);
+        assert_eq!(analysis.gaps.intervals(), &[Interval::new(10, 11)]);
+    }
+
+    #[test]
+    fn roster_analysis_equal_assignments_contain_each_other() {
+        let roster = [
+            Interval::new(3, 5),
+            Interval::new(3, 5),
+            Interval::new(3, 4),
+        ];
+
+        let analysis = analyze_roster(&roster);
+
+        assert_eq!(analysis.relations[0].contained_by, vec![1]);
+        assert_eq!(analysis.relations[1].contained_by, vec![0]);
+        assert_eq!(analysis.relations[2].contained_by, vec![0, 1]);
+        assert!(analysis.gaps.is_empty());
+    }
+
+    #[test]
+    fn roster_analysis_full_input() {
+        let lines = map_lines_to_strings("src/day04/input");
+        let roster = parse_roster(&lines);
+        let analysis = analyze_roster(&roster);
+
+        let contained_pairs = (0..lines.len())
+            .filter(|&line| {
+                let (a, b) = (2 * line, 2 * line + 1);
+                analysis.relations[a].contained_by.contains(&b)
+                    || analysis.relations[b].contained_by.contains(&a)
+            })
+            .count();
+
+        assert_eq!(contained_pairs, 466);
+    }
+
+    #[test]
+    fn it_works_const() {
+        let lines = map_lines_to_strings("src/day04/input");
+
+        assert_eq!(solve_const(include_str!("./input")), solve(&lines));
+        assert_eq!(solve_const("2-4,6-8\n2-3,4-5\n"), (0, 0));
+        assert_eq!(solve_const("\n2-4,6-8\n\n\n2-3,4-5\n\n"), (0, 0));
+        assert_eq!(solve_const("2-8,3-7\r\n\r\n5-7,7-9\r\n"), (1, 2));
+    }
+
+    #[cfg(feature = "embedded-inputs")]
+    #[test]
+    fn embedded_matches_runtime() {
+        let lines = map_lines_to_strings("src/day04/input");
+
+        assert_eq!(EMBEDDED, solve(&lines));
+    }
 }
@@ -1,47 +1,374 @@
-fn parse_state(state: &str) -> Vec<Vec<char>> {
-    let vec: Vec<_> = state.lines().collect();
-
-    let mut state = (0..9)
-        .map(|_| Vec::with_capacity(50))
-        .collect::<Vec<Vec<char>>>();
-
-    for i in 0..9 {
-        let index = 1 + (4 * i);
-        for r in &vec {
-            let x = r.as_bytes()[index];
-            if x.is_ascii_uppercase() {
-                state[i].insert(0, x as char)
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ParseError {
+    MissingFooter,
+    DuplicateLabel(String),
+    UnterminatedCrate { line: usize, column: usize },
+    UnalignedCrate { line: usize, column: usize },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Stacks {
+    pub labels: Vec<String>,
+    // Crates from bottom to top
+    pub stacks: Vec<Vec<String>>,
+}
+
+// Whitespace separated tokens with their char column spans
+fn tokens(line: &str) -> Vec<(usize, usize, String)> {
+    let mut result = vec![];
+    let mut current: Option<(usize, String)> = None;
+
+    for (col, c) in line.chars().enumerate() {
+        if c.is_whitespace() {
+            if let Some((start, token)) = current.take() {
+                result.push((start, col - 1, token));
             }
+        } else {
+            current
+                .get_or_insert_with(|| (col, String::new()))
+                .1
+                .push(c);
         }
     }
 
-    state
+    if let Some((start, token)) = current {
+        let end = start + token.chars().count() - 1;
+        result.push((start, end, token));
+    }
+
+    result
 }
 
-pub fn solve(input: &str, is_part_2: bool) -> Option<String> {
-    let (state, instrs) = input.split_once("\n\n").expect("No double newline found");
+impl Stacks {
+    pub fn parse(drawing: &str) -> Result<Stacks, ParseError> {
+        let rows: Vec<(usize, &str)> = drawing
+            .lines()
+            .enumerate()
+            .filter(|(_, l)| !l.trim().is_empty())
+            .collect();
+        let (&(_, footer), crate_rows) = rows.split_last().ok_or(ParseError::MissingFooter)?;
+
+        let footer = tokens(footer);
+        if footer.is_empty() || footer.iter().any(|(_, _, t)| t.starts_with('[')) {
+            return Err(ParseError::MissingFooter);
+        }
+
+        let mut labels: Vec<String> = vec![];
+        for (_, _, label) in &footer {
+            if labels.contains(label) {
+                return Err(ParseError::DuplicateLabel(label.clone()));
+            }
+            labels.push(label.clone());
+        }
+
+        let mut stacks = vec![vec![]; labels.len()];
+
+        for &(row, line) in crate_rows.iter().rev() {
+            let chars: Vec<char> = line.chars().collect();
+            let mut col = 0;
+
+            while col < chars.len() {
+                if chars[col].is_whitespace() {
+                    col += 1;
+                    continue;
+                }
+
+                if chars[col] != '[' {
+                    return Err(ParseError::UnalignedCrate {
+                        line: row,
+                        column: col,
+                    });
+                }
+
+                let close = chars[col..]
+                    .iter()
+                    .position(|&c| c == ']')
+                    .map(|offset| col + offset)
+                    .ok_or(ParseError::UnterminatedCrate {
+                        line: row,
+                        column: col,
+                    })?;
+
+                let stack = footer
+                    .iter()
+                    .position(|&(start, end, _)| start <= close && col <= end)
+                    .ok_or(ParseError::UnalignedCrate {
+                        line: row,
+                        column: col,
+                    })?;
 
-    let mut state = parse_state(state);
+                stacks[stack].push(chars[col + 1..close].iter().collect());
+                col = close + 1;
+            }
+        }
+
+        Ok(Stacks { labels, stacks })
+    }
+
+    pub fn index_of(&self, label: &str) -> Option<usize> {
+        self.labels.iter().position(|l| l == label)
+    }
 
-    for l in instrs.lines() {
-        let mut inst = l.split_whitespace().filter_map(|i| i.parse::<u32>().ok());
+    pub fn tops(&self) -> String {
+        self.stacks
+            .iter()
+            .filter_map(|s| s.last())
+            .cloned()
+            .collect()
+    }
+}
+
+impl fmt::Display for Stacks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = self
+            .stacks
+            .iter()
+            .flatten()
+            .map(|c| c.chars().count() + 2)
+            .chain(self.labels.iter().map(|l| l.chars().count()))
+            .max()
+            .unwrap_or(0);
+        let height = self.stacks.iter().map(|s| s.len()).max().unwrap_or(0);
+
+        for level in (0..height).rev() {
+            let row: Vec<String> = self
+                .stacks
+                .iter()
+                .map(|stack| match stack.get(level) {
+                    Some(c) => format!("{:^width$}", format!("[{}]", c)),
+                    None => " ".repeat(width),
+                })
+                .collect();
+            writeln!(f, "{}", row.join(" ").trim_end())?;
+        }
 
-        let (num, from, to) = (
-            inst.next()? as usize,
-            inst.next()? as usize - 1,
-            inst.next()? as usize - 1,
+        let footer: Vec<String> = self
+            .labels
+            .iter()
+            .map(|l| format!("{:^width$}", l))
+            .collect();
+        write!(f, "{}", footer.join(" ").trim_end())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Move {
+    pub count: usize,
+    pub from: String,
+    pub to: String,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum MoveError {
+    Malformed(String),
+    UnknownStack(String),
+    NotEnoughCrates {
+        stack: String,
+        requested: usize,
+        available: usize,
+    },
+}
+
+impl FromStr for Move {
+    type Err = MoveError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_whitespace().collect::<Vec<_>>()[..] {
+            ["move", count, "from", from, "to", to] => Ok(Move {
+                count: count
+                    .parse()
+                    .map_err(|_| MoveError::Malformed(s.to_string()))?,
+                from: from.to_string(),
+                to: to.to_string(),
+            }),
+            _ => Err(MoveError::Malformed(s.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for Move {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "move {} from {} to {}", self.count, self.from, self.to)
+    }
+}
+
+pub trait Crane {
+    // Takes the lifted crates (bottom to top, as they stood on the source stack)
+    // and returns them in the order they end up on the destination stack
+    fn arrange(&self, lifted: Vec<String>) -> Vec<String>;
+}
+
+pub struct CrateMover9000;
+
+pub struct CrateMover9001;
+
+// Lifts at most `capacity` crates at a time, keeping their order within each grab
+pub struct LimitedGrab {
+    capacity: usize,
+}
+
+impl LimitedGrab {
+    pub fn new(capacity: usize) -> Self {
+        assert!(
+            capacity > 0,
+            "Crane must be able to lift at least one crate"
         );
+        LimitedGrab { capacity }
+    }
+}
 
-        let idx = state[from].len() - num;
-        let c = state[from].split_off(idx);
-        if is_part_2 {
-            state[to].extend(c.iter());
-        } else {
-            state[to].extend(c.iter().rev());
+impl Crane for CrateMover9000 {
+    fn arrange(&self, mut lifted: Vec<String>) -> Vec<String> {
+        lifted.reverse();
+        lifted
+    }
+}
+
+impl Crane for CrateMover9001 {
+    fn arrange(&self, lifted: Vec<String>) -> Vec<String> {
+        lifted
+    }
+}
+
+impl Crane for LimitedGrab {
+    fn arrange(&self, lifted: Vec<String>) -> Vec<String> {
+        lifted
+            .rchunks(self.capacity)
+            .flat_map(|grab| grab.iter().cloned())
+            .collect()
+    }
+}
+
+impl<C: Crane + ?Sized> Crane for &C {
+    fn arrange(&self, lifted: Vec<String>) -> Vec<String> {
+        (**self).arrange(lifted)
+    }
+}
+
+struct Step {
+    from: usize,
+    to: usize,
+    lifted: Vec<String>,
+}
+
+pub struct Yard<C: Crane> {
+    crane: C,
+    initial: Stacks,
+    stacks: Stacks,
+    history: Vec<(Move, Step)>,
+}
+
+impl<C: Crane> Yard<C> {
+    pub fn new(stacks: Stacks, crane: C) -> Self {
+        Yard {
+            crane,
+            initial: stacks.clone(),
+            stacks,
+            history: vec![],
+        }
+    }
+
+    pub fn stacks(&self) -> &Stacks {
+        &self.stacks
+    }
+
+    pub fn history(&self) -> impl Iterator<Item = &Move> {
+        self.history.iter().map(|(m, _)| m)
+    }
+
+    pub fn apply(&mut self, mv: Move) -> Result<(), MoveError> {
+        let index_of = |label: &str| {
+            self.stacks
+                .index_of(label)
+                .ok_or_else(|| MoveError::UnknownStack(label.to_string()))
+        };
+        let (from, to) = (index_of(&mv.from)?, index_of(&mv.to)?);
+
+        let available = self.stacks.stacks[from].len();
+        if mv.count > available {
+            return Err(MoveError::NotEnoughCrates {
+                stack: mv.from,
+                requested: mv.count,
+                available,
+            });
+        }
+
+        let lifted = self.stacks.stacks[from].split_off(available - mv.count);
+        let arranged = self.crane.arrange(lifted.clone());
+        self.stacks.stacks[to].extend(arranged);
+
+        self.history.push((mv, Step { from, to, lifted }));
+
+        Ok(())
+    }
+
+    pub fn undo(&mut self) -> Option<Move> {
+        let (mv, step) = self.history.pop()?;
+
+        let len = self.stacks.stacks[step.to].len();
+        self.stacks.stacks[step.to].truncate(len - step.lifted.len());
+        self.stacks.stacks[step.from].extend(step.lifted);
+
+        Some(mv)
+    }
+
+    // The stacks as they were after the first `steps` moves
+    pub fn stacks_after(&self, steps: usize) -> Stacks {
+        let mut replay = Yard::new(self.initial.clone(), &self.crane);
+        for (mv, _) in self.history.iter().take(steps) {
+            replay
+                .apply(mv.clone())
+                .expect("Recorded move should replay");
         }
+        replay.stacks
     }
+}
 
-    Some(state.iter().map(|row| row.last().unwrap()).collect())
+#[derive(Debug, PartialEq, Eq)]
+pub enum RunError {
+    // No blank line between the drawing and the moves
+    MissingMoves,
+    Drawing(ParseError),
+    // `line` counts from the start of the input, from zero like `ParseError`
+    Move {
+        line: usize,
+        instruction: String,
+        error: MoveError,
+    },
+}
+
+pub fn run<C: Crane>(input: &str, crane: C) -> Result<Yard<C>, RunError> {
+    let (state, instrs) = input.split_once("\n\n").ok_or(RunError::MissingMoves)?;
+
+    let mut yard = Yard::new(Stacks::parse(state).map_err(RunError::Drawing)?, crane);
+    let first_line = state.lines().count() + 1;
+
+    for (index, l) in instrs.lines().enumerate() {
+        l.parse()
+            .and_then(|mv| yard.apply(mv))
+            .map_err(|error| RunError::Move {
+                line: first_line + index,
+                instruction: l.to_string(),
+                error,
+            })?;
+    }
+
+    Ok(yard)
+}
+
+pub fn solve(input: &str, is_part_2: bool) -> Option<String> {
+    if is_part_2 {
+        run(input, CrateMover9001)
+            .ok()
+            .map(|yard| yard.stacks().tops())
+    } else {
+        run(input, CrateMover9000)
+            .ok()
+            .map(|yard| yard.stacks().tops())
+    }
 }
 
 #[cfg(test)]
@@ -81,4 +408,178 @@ mod tests {
 
         assert_eq!(p1, "BNTZFPMMW");
     }
+
+    #[test]
+    fn parses_example_drawing() {
+        let drawing = "    [D]    \n[N] [C]    \n[Z] [M] [P]\n 1   2   3 ";
+
+        let stacks = Stacks::parse(drawing).unwrap();
+
+        assert_eq!(stacks.labels, vec!["1", "2", "3"]);
+        assert_eq!(
+            stacks.stacks,
+            vec![vec!["Z", "N"], vec!["M", "C", "D"], vec!["P"]]
+        );
+        assert_eq!(stacks.tops(), "NDP");
+    }
+
+    #[test]
+    fn parses_ragged_lines_and_long_labels() {
+        let drawing = "[AB]\n[CD]      [EF]\n [1]  [x]  [GH]\n  1    2    10";
+
+        let stacks = Stacks::parse(drawing).unwrap();
+
+        assert_eq!(stacks.labels, vec!["1", "2", "10"]);
+        assert_eq!(
+            stacks.stacks,
+            vec![vec!["1", "CD", "AB"], vec!["x"], vec!["GH", "EF"]]
+        );
+        assert_eq!(stacks.index_of("10"), Some(2));
+    }
+
+    #[test]
+    fn rejects_broken_drawings() {
+        assert_eq!(Stacks::parse(""), Err(ParseError::MissingFooter));
+        assert_eq!(Stacks::parse("[A]\n[B]"), Err(ParseError::MissingFooter));
+        assert_eq!(
+            Stacks::parse("[A\n 1 "),
+            Err(ParseError::UnterminatedCrate { line: 0, column: 0 })
+        );
+        assert_eq!(
+            Stacks::parse("        [A]\n 1   2 "),
+            Err(ParseError::UnalignedCrate { line: 0, column: 8 })
+        );
+        assert_eq!(
+            Stacks::parse(" 1   1 "),
+            Err(ParseError::DuplicateLabel(String::from("1")))
+        );
+    }
+
+    #[test]
+    fn it_works_simple() {
+        let input = "    [D]    \n[N] [C]    \n[Z] [M] [P]\n 1   2   3 \n\nmove 1 from 2 to 1\nmove 3 from 1 to 3\nmove 2 from 2 to 1\nmove 1 from 1 to 2";
+
+        assert_eq!(solve(input, false).unwrap(), "CMZ");
+        assert_eq!(solve(input, true).unwrap(), "MCD");
+    }
+
+    #[test]
+    fn renders_original_drawing() {
+        let lines = read_file_to_string("src/day05/input");
+        let (drawing, _) = lines.split_once("\n\n").unwrap();
+
+        let stacks = Stacks::parse(drawing).unwrap();
+
+        assert_eq!(stacks.to_string(), drawing);
+    }
+
+    #[test]
+    fn limited_grab_matches_both_crate_movers() {
+        let lines = read_file_to_string("src/day05/input");
+
+        let single = run(&lines, LimitedGrab::new(1)).unwrap();
+        let unlimited = run(&lines, LimitedGrab::new(usize::MAX)).unwrap();
+
+        assert_eq!(single.stacks().tops(), "PSNRGBTFT");
+        assert_eq!(unlimited.stacks().tops(), "BNTZFPMMW");
+    }
+
+    #[test]
+    fn limited_grab_moves_in_batches() {
+        let lifted = ["A", "B", "C", "D", "E"].map(String::from).to_vec();
+
+        assert_eq!(
+            LimitedGrab::new(2).arrange(lifted),
+            vec!["D", "E", "B", "C", "A"]
+        );
+    }
+
+    #[test]
+    fn invalid_moves_are_rejected() {
+        let stacks = Stacks::parse("[A]\n 1   2 ").unwrap();
+        let mut yard = Yard::new(stacks, CrateMover9000);
+
+        assert_eq!(
+            "move 2 from 1 to 2".parse().map(|mv| yard.apply(mv)),
+            Ok(Err(MoveError::NotEnoughCrates {
+                stack: String::from("1"),
+                requested: 2,
+                available: 1
+            }))
+        );
+        assert_eq!(
+            "move 1 from 3 to 2".parse().map(|mv| yard.apply(mv)),
+            Ok(Err(MoveError::UnknownStack(String::from("3"))))
+        );
+        assert_eq!(
+            "move one from 1 to 2".parse::<Move>(),
+            Err(MoveError::Malformed(String::from("move one from 1 to 2")))
+        );
+        assert_eq!(yard.history().count(), 0);
+    }
+
+    #[test]
+    fn run_reports_where_it_failed() {
+        let drawing = "    [D]    \n[N] [C]    \n[Z] [M] [P]\n 1   2   3 ";
+
+        assert_eq!(
+            run(drawing, CrateMover9000).err(),
+            Some(RunError::MissingMoves)
+        );
+        assert_eq!(
+            run("[A\n 1 \n\nmove 1 from 1 to 1", CrateMover9000).err(),
+            Some(RunError::Drawing(ParseError::UnterminatedCrate {
+                line: 0,
+                column: 0
+            }))
+        );
+
+        let input = format!("{}\n\nmove 1 from 2 to 1\nmove 3 from 2 to 3", drawing);
+        assert_eq!(
+            run(&input, CrateMover9000).err(),
+            Some(RunError::Move {
+                line: 6,
+                instruction: String::from("move 3 from 2 to 3"),
+                error: MoveError::NotEnoughCrates {
+                    stack: String::from("2"),
+                    requested: 3,
+                    available: 2
+                }
+            })
+        );
+        assert_eq!(input.lines().nth(6), Some("move 3 from 2 to 3"));
+
+        let input = format!("{}\n\nmove 1 from 2 to 1\nmove 1 from 4 to 1", drawing);
+        assert_eq!(
+            run(&input, CrateMover9000).err(),
+            Some(RunError::Move {
+                line: 6,
+                instruction: String::from("move 1 from 4 to 1"),
+                error: MoveError::UnknownStack(String::from("4"))
+            })
+        );
+        assert_eq!(solve(&input, false), None);
+    }
+
+    #[test]
+    fn history_can_be_undone_and_replayed() {
+        let input = "    [D]    \n[N] [C]    \n[Z] [M] [P]\n 1   2   3 \n\nmove 1 from 2 to 1\nmove 3 from 1 to 3\nmove 2 from 2 to 1\nmove 1 from 1 to 2";
+        let mut yard = run(input, CrateMover9001).unwrap();
+
+        assert_eq!(
+            yard.stacks_after(2).to_string(),
+            "        [D]\n        [N]\n    [C] [Z]\n    [M] [P]\n 1   2   3"
+        );
+
+        assert_eq!(yard.undo().unwrap().to_string(), "move 1 from 1 to 2");
+        assert_eq!(yard.undo().unwrap().to_string(), "move 2 from 2 to 1");
+        assert_eq!(yard.stacks(), &yard.stacks_after(2));
+
+        while yard.undo().is_some() {}
+
+        assert_eq!(
+            yard.stacks().to_string(),
+            "    [D]\n[N] [C]\n[Z] [M] [P]\n 1   2   3"
+        );
+    }
 }
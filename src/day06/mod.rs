@@ -1,3 +1,11 @@
+use std::io::{self, BufReader, Read};
+
+#[cfg(feature = "embedded-inputs")]
+pub const EMBEDDED: (Option<usize>, Option<usize>) = (
+    solve(include_str!("./input"), false),
+    solve(include_str!("./input"), true),
+);
+
 const fn find_first_non_uniq(slice: &[u8], from: usize, to: usize) -> Option<usize> {
     let mut i = from;
 
@@ -15,12 +23,15 @@ const fn find_first_non_uniq(slice: &[u8], from: usize, to: usize) -> Option<usi
     return None;
 }
 
-pub const fn solve(input: &str, is_part_2: bool) -> Option<usize> {
-    let length = if is_part_2 { 14usize } else { 4usize };
+// An empty window never counts as a marker
+pub const fn find_marker(input: &str, length: usize) -> Option<usize> {
+    if length == 0 {
+        return None;
+    }
 
     let mut i = 0usize;
 
-    while i < input.len() {
+    while i + length <= input.len() {
         if let Some(idx) = find_first_non_uniq(input.as_bytes(), i, i + length) {
             i = idx + 1;
         } else {
@@ -28,7 +39,108 @@ pub const fn solve(input: &str, is_part_2: bool) -> Option<usize> {
         }
     }
 
-    return None;
+    None
+}
+
+pub const fn solve(input: &str, is_part_2: bool) -> Option<usize> {
+    let length = if is_part_2 { 14usize } else { 4usize };
+
+    find_marker(input, length)
+}
+
+pub struct MarkerDetector {
+    window: Vec<u8>,
+    counts: [usize; 256],
+    duplicates: usize,
+    position: usize,
+}
+
+impl MarkerDetector {
+    pub fn new(length: usize) -> Self {
+        MarkerDetector {
+            window: vec![0; length],
+            counts: [0; 256],
+            duplicates: 0,
+            position: 0,
+        }
+    }
+
+    // Number of bytes seen so far
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    // Returns true if the last `length` bytes, ending with `byte`, are all different
+    pub fn push(&mut self, byte: u8) -> bool {
+        let length = self.window.len();
+        if length == 0 {
+            // Like `find_marker`, an empty window is never a marker
+            self.position += 1;
+            return false;
+        }
+
+        let slot = self.position % length;
+
+        if self.position >= length {
+            let old = self.window[slot] as usize;
+            self.counts[old] -= 1;
+            if self.counts[old] == 1 {
+                self.duplicates -= 1;
+            }
+        }
+
+        self.window[slot] = byte;
+        self.counts[byte as usize] += 1;
+        if self.counts[byte as usize] == 2 {
+            self.duplicates += 1;
+        }
+        self.position += 1;
+
+        self.position >= length && self.duplicates == 0
+    }
+}
+
+pub struct Markers<I> {
+    bytes: I,
+    detector: MarkerDetector,
+}
+
+impl<I: Iterator<Item = u8>> Iterator for Markers<I> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        for byte in self.bytes.by_ref() {
+            if self.detector.push(byte) {
+                return Some(self.detector.position());
+            }
+        }
+        None
+    }
+}
+
+pub fn markers<I: IntoIterator<Item = u8>>(bytes: I, length: usize) -> Markers<I::IntoIter> {
+    Markers {
+        bytes: bytes.into_iter(),
+        detector: MarkerDetector::new(length),
+    }
+}
+
+pub fn markers_in_reader<R: Read>(
+    reader: R,
+    length: usize,
+) -> impl Iterator<Item = io::Result<usize>> {
+    let mut detector = MarkerDetector::new(length);
+
+    BufReader::new(reader)
+        .bytes()
+        .filter_map(move |byte| match byte {
+            Ok(byte) => detector.push(byte).then(|| Ok(detector.position())),
+            Err(e) => Some(Err(e)),
+        })
+}
+
+pub fn find_marker_in_reader<R: Read>(reader: R, length: usize) -> io::Result<Option<usize>> {
+    markers_in_reader(reader, length).next().transpose()
 }
 
 #[cfg(test)]
@@ -37,6 +149,16 @@ mod tests {
     use crate::common::read_file_to_string;
     use test::{black_box, Bencher};
 
+    #[test]
+    fn zero_length_has_no_marker() {
+        let input = "mjqjpqmgbljsphdztnvjfqwrcgsmlb";
+
+        assert_eq!(find_marker(input, 0), None);
+        assert_eq!(markers(input.bytes(), 0).next(), None);
+        assert_eq!(markers_in_reader(input.as_bytes(), 0).count(), 0);
+        assert_eq!(find_marker_in_reader(input.as_bytes(), 0).unwrap(), None);
+    }
+
     #[test]
     fn uniq_works() {
         assert_eq!(find_first_non_uniq("mjqj".as_bytes(), 0, 4), Some(1));
@@ -104,4 +226,52 @@ mod tests {
             assert_eq!(RES, Some(3697));
         })
     }
+
+    #[test]
+    fn streaming_matches_const() {
+        let lines = read_file_to_string("src/day06/input");
+
+        for length in [1, 4, 14, 20] {
+            assert_eq!(
+                markers(lines.bytes(), length).next(),
+                find_marker(&lines, length)
+            );
+        }
+    }
+
+    #[test]
+    fn reports_every_marker() {
+        let all: Vec<_> = markers("aabcbcdd".bytes(), 3).collect();
+
+        assert_eq!(all, vec![4, 7]);
+        assert_eq!(markers("aaaa".bytes(), 2).next(), None);
+    }
+
+    #[test]
+    fn it_works_from_reader() {
+        let file = std::fs::File::open("src/day06/input").unwrap();
+        let p2 = find_marker_in_reader(file, 14).unwrap();
+
+        assert_eq!(p2, Some(3697));
+        assert_eq!(
+            find_marker_in_reader("bvwbjplbgvbhsrlpgdmjqwftvncz".as_bytes(), 4).unwrap(),
+            Some(5)
+        );
+    }
+
+    #[bench]
+    fn bench_part2_streaming(b: &mut Bencher) {
+        let lines = read_file_to_string("src/day06/input");
+        b.iter(|| {
+            black_box(markers(lines.bytes(), 14).next());
+        })
+    }
+
+    #[cfg(feature = "embedded-inputs")]
+    #[test]
+    fn embedded_matches_runtime() {
+        let lines = read_file_to_string("src/day06/input");
+
+        assert_eq!(EMBEDDED, (solve(&lines, false), solve(&lines, true)));
+    }
 }
@@ -1,65 +1,460 @@
-use std::collections::{HashMap, HashSet};
-use std::path::PathBuf;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::{Component, Path, PathBuf};
 
-pub fn solve_1(input: &str) -> usize {
-    parse_input(input).values().filter(|&&x| x <= 100_000).sum()
+pub type NodeId = usize;
+
+#[derive(Debug)]
+pub enum NodeKind {
+    Dir(BTreeMap<String, NodeId>),
+    File(usize),
 }
 
-pub fn solve_2(input: &str) -> usize {
-    let total_space: usize = 70_000_000;
-    let need: usize = 30_000_000;
+#[derive(Debug)]
+pub struct Node {
+    pub name: String,
+    pub parent: Option<NodeId>,
+    pub kind: NodeKind,
+}
 
-    let sizes = parse_input(input);
-    let space_used = *sizes.values().max().unwrap();
+impl Node {
+    pub fn is_dir(&self) -> bool {
+        matches!(self.kind, NodeKind::Dir(_))
+    }
+}
 
-    let space_to_delete = need - (total_space - space_used);
+#[derive(Debug, PartialEq, Eq)]
+pub enum Inconsistency {
+    UnknownCommand {
+        line: usize,
+        command: String,
+    },
+    UnexpectedOutput {
+        line: usize,
+        output: String,
+    },
+    UnlistedDirectory {
+        line: usize,
+        path: PathBuf,
+    },
+    ConflictingSize {
+        line: usize,
+        path: PathBuf,
+        known: usize,
+        listed: usize,
+    },
+    TypeConflict {
+        line: usize,
+        path: PathBuf,
+    },
+    AboveRoot {
+        line: usize,
+    },
+}
 
-    *sizes
-        .values()
-        .filter(|&&x| x > space_to_delete)
-        .min()
-        .unwrap()
+#[derive(Debug)]
+pub struct FileSystem {
+    nodes: Vec<Node>,
 }
 
-fn parse_input(input: &str) -> HashMap<PathBuf, usize> {
-    let mut dir_sizes: HashMap<PathBuf, usize> = HashMap::new();
-    let mut seen_files: HashSet<PathBuf> = HashSet::new();
-    let mut cwd = PathBuf::new();
+impl Default for FileSystem {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FileSystem {
+    pub const ROOT: NodeId = 0;
+
+    pub fn new() -> Self {
+        FileSystem {
+            nodes: vec![Node {
+                name: String::from("/"),
+                parent: None,
+                kind: NodeKind::Dir(BTreeMap::new()),
+            }],
+        }
+    }
 
-    for command_with_result in input.split('$').skip(1) {
-        let (command, result) = command_with_result.split_once('\n').unwrap();
+    pub fn from_transcript(input: &str) -> Self {
+        FileSystem::validate_transcript(input).0
+    }
 
-        match command.trim() {
-            "ls" => {
-                for line in result.lines() {
-                    let (size, name) = line.split_once(' ').unwrap();
+    // Builds as much of the tree as possible, reporting everything that doesn't add up
+    pub fn validate_transcript(input: &str) -> (Self, Vec<Inconsistency>) {
+        let mut fs = FileSystem::new();
+        let mut issues = vec![];
+        let mut cwd = FileSystem::ROOT;
+        let mut listing = false;
 
-                    if size != "dir" {
-                        let abs_path = cwd.join(name);
-                        if !seen_files.contains(&abs_path) {
-                            seen_files.insert(abs_path.clone());
-                            let size: usize = size.parse().unwrap();
+        for (index, line) in input.lines().enumerate() {
+            let line_no = index + 1;
 
-                            for p in cwd.ancestors() {
-                                let path = PathBuf::from(p);
+            if let Some(command) = line.strip_prefix('$') {
+                listing = false;
 
-                                *dir_sizes.entry(path.clone()).or_insert(0) += size;
-                            }
+                match command.trim().split_once(' ') {
+                    None if command.trim() == "ls" => listing = true,
+                    Some(("cd", "/")) => cwd = FileSystem::ROOT,
+                    Some(("cd", "..")) => match fs.nodes[cwd].parent {
+                        Some(parent) => cwd = parent,
+                        None => issues.push(Inconsistency::AboveRoot { line: line_no }),
+                    },
+                    Some(("cd", dir)) => match fs.child(cwd, dir) {
+                        Some(id) if fs.nodes[id].is_dir() => cwd = id,
+                        Some(id) => issues.push(Inconsistency::TypeConflict {
+                            line: line_no,
+                            path: fs.path(id),
+                        }),
+                        None => {
+                            cwd = fs.mkdir(cwd, dir);
+                            issues.push(Inconsistency::UnlistedDirectory {
+                                line: line_no,
+                                path: fs.path(cwd),
+                            });
                         }
+                    },
+                    _ => issues.push(Inconsistency::UnknownCommand {
+                        line: line_no,
+                        command: command.trim().to_string(),
+                    }),
+                }
+                continue;
+            }
+
+            let entry = line.split_once(' ').and_then(|(size, name)| match size {
+                "dir" => Some((None, name)),
+                size => size.parse::<usize>().ok().map(|size| (Some(size), name)),
+            });
+
+            match (listing, entry) {
+                (true, Some((size, name))) => match (fs.child(cwd, name), size) {
+                    (None, None) => {
+                        fs.mkdir(cwd, name);
+                    }
+                    (None, Some(size)) => {
+                        fs.touch(cwd, name, size);
                     }
+                    (Some(id), size) => match (&fs.nodes[id].kind, size) {
+                        (NodeKind::Dir(_), None) => {}
+                        (NodeKind::File(known), Some(listed)) if *known != listed => {
+                            issues.push(Inconsistency::ConflictingSize {
+                                line: line_no,
+                                path: fs.path(id),
+                                known: *known,
+                                listed,
+                            })
+                        }
+                        (NodeKind::File(_), Some(_)) => {}
+                        _ => issues.push(Inconsistency::TypeConflict {
+                            line: line_no,
+                            path: fs.path(id),
+                        }),
+                    },
+                },
+                _ => issues.push(Inconsistency::UnexpectedOutput {
+                    line: line_no,
+                    output: line.to_string(),
+                }),
+            }
+        }
+
+        (fs, issues)
+    }
+
+    pub fn node(&self, id: NodeId) -> &Node {
+        &self.nodes[id]
+    }
+
+    pub fn child(&self, dir: NodeId, name: &str) -> Option<NodeId> {
+        match &self.nodes[dir].kind {
+            NodeKind::Dir(children) => children.get(name).copied(),
+            NodeKind::File(_) => None,
+        }
+    }
+
+    fn add_child(&mut self, dir: NodeId, name: &str, kind: NodeKind) -> NodeId {
+        let id = self.nodes.len();
+        self.nodes.push(Node {
+            name: name.to_string(),
+            parent: Some(dir),
+            kind,
+        });
+
+        match &mut self.nodes[dir].kind {
+            NodeKind::Dir(children) => children.insert(name.to_string(), id),
+            NodeKind::File(_) => panic!("{:?} is not a directory", self.path(dir)),
+        };
+
+        id
+    }
+
+    // Returns the existing directory if there already is one with that name
+    pub fn mkdir(&mut self, dir: NodeId, name: &str) -> NodeId {
+        match self.child(dir, name) {
+            Some(id) => id,
+            None => self.add_child(dir, name, NodeKind::Dir(BTreeMap::new())),
+        }
+    }
+
+    // Files that are already known keep their original size
+    pub fn touch(&mut self, dir: NodeId, name: &str, size: usize) -> NodeId {
+        match self.child(dir, name) {
+            Some(id) => id,
+            None => self.add_child(dir, name, NodeKind::File(size)),
+        }
+    }
+
+    pub fn path(&self, id: NodeId) -> PathBuf {
+        let mut names = vec![];
+        let mut current = Some(id);
+
+        while let Some(id) = current {
+            names.push(self.nodes[id].name.as_str());
+            current = self.nodes[id].parent;
+        }
+
+        names.iter().rev().collect()
+    }
+
+    pub fn lookup(&self, path: &Path) -> Option<NodeId> {
+        path.components()
+            .try_fold(FileSystem::ROOT, |id, component| match component {
+                Component::RootDir | Component::CurDir => Some(id),
+                Component::ParentDir => Some(self.nodes[id].parent.unwrap_or(id)),
+                Component::Normal(name) => self.child(id, name.to_str()?),
+                Component::Prefix(_) => None,
+            })
+    }
+
+    // Total size of every node, indexed by `NodeId`
+    fn sizes(&self) -> Vec<usize> {
+        let mut sizes: Vec<usize> = self
+            .nodes
+            .iter()
+            .map(|n| match n.kind {
+                NodeKind::File(size) => size,
+                NodeKind::Dir(_) => 0,
+            })
+            .collect();
+
+        // Children are always created after their parent
+        for id in (1..self.nodes.len()).rev() {
+            if let Some(parent) = self.nodes[id].parent {
+                sizes[parent] += sizes[id];
+            }
+        }
+
+        sizes
+    }
+
+    pub fn du(&self, id: NodeId) -> usize {
+        match &self.nodes[id].kind {
+            NodeKind::File(size) => *size,
+            NodeKind::Dir(children) => children.values().map(|&c| self.du(c)).sum(),
+        }
+    }
+
+    pub fn dir_sizes(&self) -> Vec<(PathBuf, usize)> {
+        self.find(|node, _| node.is_dir())
+    }
+
+    pub fn find<F>(&self, predicate: F) -> Vec<(PathBuf, usize)>
+    where
+        F: Fn(&Node, usize) -> bool,
+    {
+        let sizes = self.sizes();
+
+        self.nodes
+            .iter()
+            .enumerate()
+            .filter(|&(id, node)| predicate(node, sizes[id]))
+            .map(|(id, _)| (self.path(id), sizes[id]))
+            .collect()
+    }
+
+    pub fn largest_files(&self, count: usize) -> Vec<(PathBuf, usize)> {
+        let mut files = self.find(|node, _| !node.is_dir());
+        files.sort_by(|(p1, s1), (p2, s2)| s2.cmp(s1).then_with(|| p1.cmp(p2)));
+        files.truncate(count);
+        files
+    }
+
+    pub fn tree(&self) -> String {
+        let mut out = String::new();
+        self.render(FileSystem::ROOT, 0, &mut out);
+        out
+    }
+
+    fn render(&self, id: NodeId, depth: usize, out: &mut String) {
+        let node = &self.nodes[id];
+        let indent = "  ".repeat(depth);
+
+        match &node.kind {
+            NodeKind::Dir(children) => {
+                writeln!(out, "{}- {} (dir)", indent, node.name).unwrap();
+                for &child in children.values() {
+                    self.render(child, depth + 1, out);
                 }
             }
-            "cd .." => {
-                cwd.pop();
+            NodeKind::File(size) => {
+                writeln!(out, "{}- {} (file, size={})", indent, node.name, size).unwrap();
             }
-            cd_dir => {
-                let (_, dir) = cd_dir.split_once(' ').unwrap();
-                cwd.push(dir);
+        }
+    }
+}
+
+pub fn solve_1(input: &str) -> usize {
+    FileSystem::from_transcript(input)
+        .find(|node, size| node.is_dir() && size <= 100_000)
+        .iter()
+        .map(|(_, size)| size)
+        .sum()
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum CleanupPlan {
+    NotNeeded {
+        free: usize,
+    },
+    Delete {
+        to_free: usize,
+        directories: Vec<(PathBuf, usize)>,
+    },
+    Impossible {
+        to_free: usize,
+    },
+}
+
+pub struct CleanupPlanner {
+    pub capacity: usize,
+    pub required: usize,
+}
+
+impl CleanupPlanner {
+    pub fn new(capacity: usize, required: usize) -> Self {
+        CleanupPlanner { capacity, required }
+    }
+
+    fn to_free(&self, fs: &FileSystem) -> Result<usize, CleanupPlan> {
+        let used = fs.du(FileSystem::ROOT);
+        let to_free = (used + self.required).saturating_sub(self.capacity);
+
+        if to_free == 0 {
+            Err(CleanupPlan::NotNeeded {
+                free: self.capacity - used,
+            })
+        } else if to_free > used {
+            Err(CleanupPlan::Impossible { to_free })
+        } else {
+            Ok(to_free)
+        }
+    }
+
+    // Every directory that alone frees enough space, smallest first
+    pub fn candidates(&self, fs: &FileSystem) -> CleanupPlan {
+        let to_free = match self.to_free(fs) {
+            Ok(to_free) => to_free,
+            Err(plan) => return plan,
+        };
+
+        let mut directories = fs.find(|node, size| node.is_dir() && size >= to_free);
+        directories.sort_by(|(p1, s1), (p2, s2)| s1.cmp(s2).then_with(|| p1.cmp(p2)));
+
+        CleanupPlan::Delete {
+            to_free,
+            directories,
+        }
+    }
+
+    // The fewest directories that together free enough space, freeing as little as
+    // possible among sets of that size; the root is only picked when nothing else will do
+    pub fn minimal_set(&self, fs: &FileSystem) -> CleanupPlan {
+        let to_free = match self.to_free(fs) {
+            Ok(to_free) => to_free,
+            Err(plan) => return plan,
+        };
+
+        let mut dirs = fs.find(|node, _| node.is_dir() && node.parent.is_some());
+        dirs.sort_by(|(p1, s1), (p2, s2)| s2.cmp(s1).then_with(|| p1.cmp(p2)));
+
+        for count in 1..=dirs.len() {
+            let mut search = SetSearch {
+                dirs: &dirs,
+                to_free,
+                chosen: vec![],
+                best: None,
+            };
+            search.run(0, 0, count);
+
+            if let Some((_, chosen)) = search.best {
+                return CleanupPlan::Delete {
+                    to_free,
+                    directories: chosen.into_iter().map(|i| dirs[i].clone()).collect(),
+                };
+            }
+        }
+
+        let root = fs.find(|node, size| node.parent.is_none() && size >= to_free);
+        if !root.is_empty() {
+            return CleanupPlan::Delete {
+                to_free,
+                directories: root,
+            };
+        }
+
+        CleanupPlan::Impossible { to_free }
+    }
+}
+
+struct SetSearch<'a> {
+    // Sorted by size, largest first
+    dirs: &'a [(PathBuf, usize)],
+    to_free: usize,
+    chosen: Vec<usize>,
+    best: Option<(usize, Vec<usize>)>,
+}
+
+impl SetSearch<'_> {
+    fn run(&mut self, start: usize, sum: usize, left: usize) {
+        if left == 0 {
+            if sum >= self.to_free && self.best.as_ref().is_none_or(|(b, _)| sum < *b) {
+                self.best = Some((sum, self.chosen.clone()));
             }
+            return;
+        }
+
+        for i in start..self.dirs.len() {
+            let (path, size) = &self.dirs[i];
+
+            // Everything after this is smaller, so it can only get worse
+            if sum + size * left < self.to_free {
+                break;
+            }
+            if self.best.as_ref().is_some_and(|(b, _)| sum + size >= *b) {
+                continue;
+            }
+            if self.chosen.iter().any(|&c| {
+                let other = &self.dirs[c].0;
+                path.starts_with(other) || other.starts_with(path)
+            }) {
+                continue;
+            }
+
+            self.chosen.push(i);
+            self.run(i + 1, sum + size, left - 1);
+            self.chosen.pop();
         }
     }
+}
+
+pub fn solve_2(input: &str) -> usize {
+    let fs = FileSystem::from_transcript(input);
 
-    return dir_sizes;
+    match CleanupPlanner::new(70_000_000, 30_000_000).candidates(&fs) {
+        CleanupPlan::Delete { directories, .. } => directories[0].1,
+        plan => panic!("Expected something to delete, got {:?}", plan),
+    }
 }
 
 #[cfg(test)]
@@ -68,9 +463,7 @@ mod tests {
 
     use super::*;
 
-    #[test]
-    fn it_works_simple() {
-        let simple = r"$ cd /
+    const EXAMPLE: &str = r"$ cd /
 $ ls
 dir a
 14848514 b.txt
@@ -93,6 +486,10 @@ $ ls
 8033020 d.log
 5626152 d.ext
 7214296 k";
+
+    #[test]
+    fn it_works_simple() {
+        let simple = EXAMPLE;
         let p1 = solve_1(&simple);
 
         assert_eq!(p1, 95437);
@@ -113,4 +510,227 @@ $ ls
 
         assert_eq!(p1, 7991939);
     }
+
+    #[test]
+    fn builds_tree_from_transcript() {
+        let fs = FileSystem::from_transcript(EXAMPLE);
+
+        assert_eq!(
+            fs.tree(),
+            r"- / (dir)
+  - a (dir)
+    - e (dir)
+      - i (file, size=584)
+    - f (file, size=29116)
+    - g (file, size=2557)
+    - h.lst (file, size=62596)
+  - b.txt (file, size=14848514)
+  - c.dat (file, size=8504156)
+  - d (dir)
+    - d.ext (file, size=5626152)
+    - d.log (file, size=8033020)
+    - j (file, size=4060174)
+    - k (file, size=7214296)
+"
+        );
+
+        let e = fs.lookup(Path::new("/a/e")).unwrap();
+        assert_eq!(fs.path(e), PathBuf::from("/a/e"));
+        assert_eq!(fs.du(e), 584);
+        assert_eq!(fs.du(FileSystem::ROOT), 48381165);
+        assert_eq!(
+            fs.lookup(Path::new("/a/../d/k")).map(|k| fs.du(k)),
+            Some(7214296)
+        );
+        assert_eq!(fs.lookup(Path::new("/x")), None);
+    }
+
+    #[test]
+    fn queries_tree() {
+        let fs = FileSystem::from_transcript(EXAMPLE);
+
+        assert_eq!(
+            fs.find(|node, size| node.is_dir() && size <= 100_000),
+            vec![(PathBuf::from("/a"), 94853), (PathBuf::from("/a/e"), 584)]
+        );
+        assert_eq!(
+            fs.largest_files(2),
+            vec![
+                (PathBuf::from("/b.txt"), 14848514),
+                (PathBuf::from("/c.dat"), 8504156)
+            ]
+        );
+    }
+
+    #[test]
+    fn cd_root_resets_to_root() {
+        let fs = FileSystem::from_transcript("$ cd /\n$ cd a\n$ cd b\n$ cd /\n$ ls\n10 x");
+
+        assert_eq!(fs.lookup(Path::new("/x")).map(|x| fs.du(x)), Some(10));
+        assert_eq!(fs.du(fs.lookup(Path::new("/a/b")).unwrap()), 0);
+    }
+
+    #[test]
+    fn validates_consistent_transcript() {
+        let (_, issues) = FileSystem::validate_transcript(EXAMPLE);
+        assert_eq!(issues, vec![]);
+
+        let lines = read_file_to_string("src/day07/input");
+        let (_, issues) = FileSystem::validate_transcript(&lines);
+        assert_eq!(issues, vec![]);
+    }
+
+    #[test]
+    fn reports_inconsistencies() {
+        let transcript = r"$ cd /
+$ ls
+dir a
+10 b
+$ cd c
+$ cd /
+$ ls
+20 b
+b 10
+dir b
+$ cd b
+$ cd ..
+$ cd ..
+$ rm -rf /
+$ cd a
+12 x";
+
+        let (fs, issues) = FileSystem::validate_transcript(transcript);
+
+        assert_eq!(
+            issues,
+            vec![
+                Inconsistency::UnlistedDirectory {
+                    line: 5,
+                    path: PathBuf::from("/c")
+                },
+                Inconsistency::ConflictingSize {
+                    line: 8,
+                    path: PathBuf::from("/b"),
+                    known: 10,
+                    listed: 20
+                },
+                Inconsistency::UnexpectedOutput {
+                    line: 9,
+                    output: String::from("b 10")
+                },
+                Inconsistency::TypeConflict {
+                    line: 10,
+                    path: PathBuf::from("/b")
+                },
+                Inconsistency::TypeConflict {
+                    line: 11,
+                    path: PathBuf::from("/b")
+                },
+                Inconsistency::AboveRoot { line: 12 },
+                Inconsistency::AboveRoot { line: 13 },
+                Inconsistency::UnknownCommand {
+                    line: 14,
+                    command: String::from("rm -rf /")
+                },
+                Inconsistency::UnexpectedOutput {
+                    line: 16,
+                    output: String::from("12 x")
+                },
+            ]
+        );
+        assert_eq!(fs.du(FileSystem::ROOT), 10);
+    }
+
+    #[test]
+    fn plans_cleanup() {
+        let fs = FileSystem::from_transcript(EXAMPLE);
+
+        assert_eq!(
+            CleanupPlanner::new(70_000_000, 30_000_000).candidates(&fs),
+            CleanupPlan::Delete {
+                to_free: 8381165,
+                directories: vec![
+                    (PathBuf::from("/d"), 24933642),
+                    (PathBuf::from("/"), 48381165)
+                ]
+            }
+        );
+        assert_eq!(
+            CleanupPlanner::new(100_000_000, 30_000_000).candidates(&fs),
+            CleanupPlan::NotNeeded { free: 51618835 }
+        );
+        assert_eq!(
+            CleanupPlanner::new(50_000_000, 60_000_000).minimal_set(&fs),
+            CleanupPlan::Impossible { to_free: 58381165 }
+        );
+
+        let lines = read_file_to_string("src/day07/input");
+        let fs = FileSystem::from_transcript(&lines);
+        match CleanupPlanner::new(70_000_000, 30_000_000).minimal_set(&fs) {
+            CleanupPlan::Delete { directories, .. } => {
+                assert_eq!(directories.len(), 1);
+                assert_eq!(directories[0].1, 7991939);
+            }
+            plan => panic!("Unexpected plan {:?}", plan),
+        }
+    }
+
+    #[test]
+    fn plans_root_as_last_resort() {
+        let fs = FileSystem::from_transcript("$ cd /\n$ ls\n100 a");
+        let planner = CleanupPlanner::new(150, 100);
+
+        let root = CleanupPlan::Delete {
+            to_free: 50,
+            directories: vec![(PathBuf::from("/"), 100)],
+        };
+        assert_eq!(planner.candidates(&fs), root);
+        assert_eq!(planner.minimal_set(&fs), root);
+
+        assert_eq!(
+            CleanupPlanner::new(150, 200).minimal_set(&fs),
+            CleanupPlan::Impossible { to_free: 150 }
+        );
+    }
+
+    #[test]
+    fn plans_minimal_set() {
+        let fs = FileSystem::from_transcript(
+            r"$ cd /
+$ ls
+dir a
+dir b
+dir c
+$ cd a
+$ ls
+40 x
+dir d
+$ cd d
+$ ls
+35 y
+$ cd /
+$ cd b
+$ ls
+50 z
+$ cd /
+$ cd c
+$ ls
+45 w",
+        );
+
+        assert_eq!(
+            CleanupPlanner::new(200, 100).minimal_set(&fs),
+            CleanupPlan::Delete {
+                to_free: 70,
+                directories: vec![(PathBuf::from("/a"), 75)]
+            }
+        );
+        assert_eq!(
+            CleanupPlanner::new(170, 100).minimal_set(&fs),
+            CleanupPlan::Delete {
+                to_free: 100,
+                directories: vec![(PathBuf::from("/a"), 75), (PathBuf::from("/c"), 45)]
+            }
+        );
+    }
 }
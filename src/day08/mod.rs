@@ -1,165 +1,123 @@
-use std::collections::HashSet;
+use std::fmt::{Display, Write};
 
-fn parse(input: &str) -> Vec<Vec<u32>> {
+pub type Grid<T> = Vec<Vec<T>>;
+
+fn parse(input: &str) -> Grid<u32> {
     input
         .lines()
         .map(|l| l.chars().map(|c| c.to_digit(10).unwrap()).collect())
         .collect()
 }
 
-pub fn solve_1(input: &str) -> usize {
-    let grid = parse(input);
-
-    let mut set = HashSet::new();
-
-    for (i, _) in grid.iter().enumerate() {
-        set.insert((i, 0));
-        set.insert((0, i));
-        set.insert((i, grid[i].len() - 1));
-        set.insert((grid[i].len() - 1, i));
-
-        let mut max_value = grid[i].first().unwrap();
-
-        for (j, _v) in grid[i][1..grid[i].len()]
-            .iter()
-            .enumerate()
-            .filter(|&(_j, x)| {
-                if x > max_value {
-                    max_value = x;
-                    true
-                } else {
-                    false
-                }
-            })
-        {
-            set.insert((i, 1 + j));
-        }
+// Every row and column of the grid, walked in both directions
+fn lines_of_sight(rows: usize, cols: usize) -> impl Iterator<Item = Vec<(usize, usize)>> {
+    let horizontal = (0..rows).map(move |y| (0..cols).map(|x| (y, x)).collect::<Vec<_>>());
+    let vertical = (0..cols).map(move |x| (0..rows).map(|y| (y, x)).collect::<Vec<_>>());
 
-        let mut max_value = grid[i].last().unwrap();
-
-        for (j, _v) in grid[i][1..grid[i].len()]
-            .iter()
-            .enumerate()
-            .rev()
-            .filter(|&(_j, x)| {
-                if x > max_value {
-                    max_value = x;
-                    true
-                } else {
-                    false
-                }
-            })
-        {
-            set.insert((i, 1 + j));
-        }
-
-        let mut max_value = grid.first().unwrap()[i];
+    horizontal
+        .chain(vertical)
+        .flat_map(|line| [line.iter().rev().copied().collect(), line])
+}
 
-        for (j, _v) in grid[1..grid.len()]
-            .iter()
-            .map(|a| &a[i])
-            .enumerate()
-            .filter(|&(_j, x)| {
-                if x > &max_value {
-                    max_value = *x;
-                    true
-                } else {
-                    false
-                }
-            })
-        {
-            set.insert((1 + j, i));
-        }
+pub fn visibility(grid: &Grid<u32>) -> Grid<bool> {
+    let cols = grid.first().map_or(0, |row| row.len());
+    let mut visible = vec![vec![false; cols]; grid.len()];
 
-        let mut max_value = grid.last().unwrap()[i];
+    for line in lines_of_sight(grid.len(), cols) {
+        let mut max_height = None;
 
-        for (j, _v) in grid[1..grid.len()]
-            .iter()
-            .map(|a| a[i])
-            .enumerate()
-            .rev()
-            .filter(|(_j, x)| {
-                if x > &max_value {
-                    max_value = *x;
-                    true
-                } else {
-                    false
-                }
-            })
-        {
-            set.insert((1 + j, i));
+        for (y, x) in line {
+            if max_height.is_none_or(|max| grid[y][x] > max) {
+                visible[y][x] = true;
+                max_height = Some(grid[y][x]);
+            }
         }
     }
 
-    set.len()
+    visible
 }
 
-pub fn solve_2(input: &str) -> usize {
-    let grid = parse(input);
+pub fn scenic_scores(grid: &Grid<u32>) -> Grid<usize> {
+    let cols = grid.first().map_or(0, |row| row.len());
+    let mut scores = vec![vec![1; cols]; grid.len()];
 
-    let mut max_score = 0;
+    for line in lines_of_sight(grid.len(), cols) {
+        // Indices into `line` of trees not yet blocked by a taller one, tallest at the bottom
+        let mut stack: Vec<usize> = vec![];
 
-    for y in 1..grid.len() {
-        for x in 1..grid.len() {
-            let cand = grid[y][x];
-
-            let mut up = 0;
-
-            for i in (0..y).rev() {
-                if grid[i][x] >= cand {
-                    up += 1;
+        for (i, &(y, x)) in line.iter().enumerate() {
+            while let Some(&top) = stack.last() {
+                let (ty, tx) = line[top];
+                if grid[ty][tx] >= grid[y][x] {
                     break;
-                } else {
-                    up += 1;
                 }
+                stack.pop();
             }
 
-            let mut down = 0;
-            for i in (y + 1)..grid.len() {
-                if grid[i][x] >= cand {
-                    down += 1;
-                    break;
-                } else {
-                    down += 1;
-                }
-            }
+            let distance = match stack.last() {
+                Some(&top) => i - top,
+                None => i,
+            };
+            scores[y][x] *= distance;
 
-            let mut left = 0;
+            stack.push(i);
+        }
+    }
 
-            for i in (0..x).rev() {
-                if grid[y][i] >= cand {
-                    left += 1;
-                    break;
-                } else {
-                    left += 1;
-                }
-            }
+    scores
+}
 
-            let mut right = 0;
+pub fn to_csv<T: Display>(grid: &[Vec<T>]) -> String {
+    grid.iter()
+        .map(|row| {
+            let cells: Vec<String> = row.iter().map(|v| v.to_string()).collect();
+            cells.join(",") + "\n"
+        })
+        .collect()
+}
 
-            for i in (x + 1)..grid.len() {
-                if grid[y][i] >= cand {
-                    right += 1;
-                    break;
-                } else {
-                    right += 1;
-                }
-            }
+// Plain (P2) greymap, brightest for the highest value
+pub fn to_pgm<T, F>(grid: &[Vec<T>], value: F) -> String
+where
+    F: Fn(&T) -> u64,
+{
+    let height = grid.len();
+    let width = grid.first().map_or(0, |row| row.len());
+    let max = grid.iter().flatten().map(&value).max().unwrap_or(0).max(1);
+    let max_gray = max.min(255);
+
+    let mut out = format!("P2\n{} {}\n{}\n", width, height, max_gray);
+    for row in grid {
+        let cells: Vec<String> = row
+            .iter()
+            .map(|v| (value(v) * max_gray / max).to_string())
+            .collect();
+        writeln!(out, "{}", cells.join(" ")).unwrap();
+    }
 
-            let score = up * down * left * right;
+    out
+}
 
-            if score > max_score {
-                max_score = score;
-            }
-        }
-    }
+pub fn solve_1(input: &str) -> usize {
+    visibility(&parse(input))
+        .iter()
+        .flatten()
+        .filter(|&&visible| visible)
+        .count()
+}
 
-    return max_score;
+pub fn solve_2(input: &str) -> usize {
+    scenic_scores(&parse(input))
+        .into_iter()
+        .flatten()
+        .max()
+        .unwrap_or(0)
 }
 
 #[cfg(test)]
 mod tests {
     use crate::common::read_file_to_string;
+    use test::Bencher;
 
     use super::*;
 
@@ -202,4 +160,77 @@ mod tests {
 
         assert_eq!(p1, 230112);
     }
+
+    #[test]
+    fn computes_grids() {
+        let grid = parse("30373\n25512\n65332\n33549\n35390");
+
+        assert_eq!(
+            visibility(&grid),
+            vec![
+                vec![true, true, true, true, true],
+                vec![true, true, true, false, true],
+                vec![true, true, false, true, true],
+                vec![true, false, true, false, true],
+                vec![true, true, true, true, true],
+            ]
+        );
+        assert_eq!(scenic_scores(&grid)[1][2], 4);
+        assert_eq!(scenic_scores(&grid)[3][2], 8);
+        assert_eq!(scenic_scores(&grid)[0], vec![0; 5]);
+    }
+
+    #[bench]
+    fn bench_part_1(b: &mut Bencher) {
+        let lines = read_file_to_string("src/day08/input");
+        b.iter(|| {
+            let p1 = solve_1(lines.trim_end());
+            assert_eq!(p1, 1845);
+        })
+    }
+
+    #[bench]
+    fn bench_part_2(b: &mut Bencher) {
+        let lines = read_file_to_string("src/day08/input");
+        b.iter(|| {
+            let p2 = solve_2(lines.trim_end());
+            assert_eq!(p2, 230112);
+        })
+    }
+
+    #[test]
+    fn handles_wide_forest() {
+        let wide = "30373\n25512\n65332";
+
+        assert_eq!(solve_1(wide), 14);
+        assert_eq!(solve_2(wide), 2);
+    }
+
+    #[test]
+    fn handles_tall_forest() {
+        let tall = "123\n456\n789\n321";
+
+        assert_eq!(solve_1(tall), 12);
+        assert_eq!(solve_2(tall), 2);
+        assert_eq!(solve_1("12\n34\n56\n78\n90"), 10);
+        assert_eq!(solve_2("12\n34\n56\n78\n90"), 0);
+    }
+
+    #[test]
+    fn exports_grids() {
+        let grid = parse("123\n456\n789\n321");
+
+        assert_eq!(
+            to_csv(&scenic_scores(&grid)),
+            "0,0,0\n0,1,0\n0,2,0\n0,0,0\n"
+        );
+        assert_eq!(
+            to_pgm(&visibility(&grid), |&v| v as u64),
+            "P2\n3 4\n1\n1 1 1\n1 1 1\n1 1 1\n1 1 1\n"
+        );
+        assert_eq!(
+            to_pgm(&[vec![0usize, 510, 1020]], |&v| v as u64),
+            "P2\n3 1\n255\n0 127 255\n"
+        );
+    }
 }
@@ -1,6 +1,8 @@
 use std::collections::HashSet;
 
-fn max_distance(p1: &(i32, i32), p2: &(i32, i32)) -> u32 {
+pub type Pos = (i32, i32);
+
+fn max_distance(p1: &Pos, p2: &Pos) -> u32 {
     let x_diff = p1.0.abs_diff(p2.0);
 
     let y_diff = p1.1.abs_diff(p2.1);
@@ -8,113 +10,195 @@ fn max_distance(p1: &(i32, i32), p2: &(i32, i32)) -> u32 {
     x_diff.max(y_diff)
 }
 
-fn parse(input: &str) -> Vec<(&str, u32)> {
+fn direction(dir: &str) -> Pos {
+    let (dx, dy): Pos = dir.chars().fold((0, 0), |(dx, dy), c| match c {
+        'R' => (dx + 1, dy),
+        'L' => (dx - 1, dy),
+        'U' => (dx, dy - 1),
+        'D' => (dx, dy + 1),
+        _ => panic!("Unknown direction {:?}", dir),
+    });
+
+    if dx.abs() > 1 || dy.abs() > 1 || (dx, dy) == (0, 0) {
+        panic!("Direction {:?} is not a single step", dir);
+    }
+
+    (dx, dy)
+}
+
+fn parse(input: &str) -> Vec<(Pos, u32)> {
     input
         .lines()
         .map(|l| {
             let (dir, steps) = l.split_once(' ').unwrap();
-            (dir, steps.parse().unwrap())
+            (direction(dir), steps.parse().unwrap())
         })
         .collect()
 }
 
-pub fn solve_1(input: &str) -> usize {
-    let moves = parse(input);
-
-    let mut set = HashSet::new();
+pub struct Rope {
+    knots: Vec<Pos>,
+    visited: Vec<HashSet<Pos>>,
+}
 
-    let mut h = (0, 0);
-    let mut t = (0, 0);
+impl Rope {
+    pub fn new(knot_count: usize) -> Self {
+        assert!(knot_count > 0, "A rope needs at least one knot");
 
-    set.insert(t);
+        Rope {
+            knots: vec![(0, 0); knot_count],
+            visited: vec![HashSet::from([(0, 0)]); knot_count],
+        }
+    }
 
-    for (dir, steps) in moves {
-        let (dx, dy) = match dir {
-            "R" => (1, 0),
-            "L" => (-1, 0),
-            "U" => (0, -1),
-            "D" => (0, 1),
-            _ => unreachable!(),
-        };
+    // The head is the first knot, the tail the last
+    pub fn knots(&self) -> &[Pos] {
+        &self.knots
+    }
 
-        for _ in 0..steps {
-            let ph = h;
-            h.0 += dx;
-            h.1 += dy;
-            if max_distance(&h, &t) > 1 {
-                t.0 = ph.0;
-                t.1 = ph.1;
+    pub fn visited(&self) -> &[HashSet<Pos>] {
+        &self.visited
+    }
 
-                set.insert(t);
-            }
-        }
+    pub fn tail_visited(&self) -> &HashSet<Pos> {
+        self.visited.last().unwrap()
     }
 
-    for y in -100..100 {
-        for x in -100..100 {
-            if set.contains(&(x, y)) {
-                print!("#")
-            } else {
-                print!(".")
+    pub fn step(&mut self, (dx, dy): Pos) {
+        self.knots[0].0 += dx;
+        self.knots[0].1 += dy;
+        self.visited[0].insert(self.knots[0]);
+
+        for i in 1..self.knots.len() {
+            let p = self.knots[i - 1];
+            let t = &mut self.knots[i];
+
+            if max_distance(&p, t) > 1 {
+                t.0 += (p.0 - t.0).signum();
+                t.1 += (p.1 - t.1).signum();
+                self.visited[i].insert(*t);
             }
         }
-        println!()
     }
-
-    set.len()
 }
 
-pub fn solve_2(input: &str) -> usize {
-    let moves = parse(input);
+pub struct Replay {
+    rope: Rope,
+    moves: std::vec::IntoIter<(Pos, u32)>,
+    current: Option<(Pos, u32)>,
+}
 
-    let mut set = HashSet::new();
+impl Replay {
+    pub fn rope(&self) -> &Rope {
+        &self.rope
+    }
 
-    let mut h = (0, 0);
-    let mut tails = [(0, 0); 9];
+    pub fn into_rope(self) -> Rope {
+        self.rope
+    }
+}
 
-    set.insert(tails[8]);
+// Yields the knot positions after every single step
+impl Iterator for Replay {
+    type Item = Vec<Pos>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.current.as_mut() {
+                Some((delta, steps)) if *steps > 0 => {
+                    *steps -= 1;
+                    self.rope.step(*delta);
+                    return Some(self.rope.knots.clone());
+                }
+                _ => self.current = Some(self.moves.next()?),
+            }
+        }
+    }
+}
 
-    for (dir, steps) in moves {
-        let (dx, dy) = match dir {
-            "R" => (1, 0),
-            "L" => (-1, 0),
-            "U" => (0, -1),
-            "D" => (0, 1),
-            _ => unreachable!(),
-        };
+pub fn replay(input: &str, knot_count: usize) -> Replay {
+    Replay {
+        rope: Rope::new(knot_count),
+        moves: parse(input).into_iter(),
+        current: None,
+    }
+}
 
-        for _ in 0..steps {
-            h.0 += dx;
-            h.1 += dy;
+pub fn simulate(input: &str, knot_count: usize) -> Rope {
+    let mut replay = replay(input, knot_count);
+    replay.by_ref().for_each(drop);
+    replay.into_rope()
+}
 
-            let mut p = h;
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Bounds {
+    pub min: Pos,
+    pub max: Pos,
+}
 
-            for t in tails.iter_mut() {
-                if max_distance(&p, t) > 1 {
-                    t.0 += (p.0 - t.0).signum();
-                    t.1 += (p.1 - t.1).signum();
-                }
-                p = *t;
-            }
+impl Bounds {
+    // Always includes the starting position
+    pub fn around<'a, I: IntoIterator<Item = &'a Pos>>(points: I) -> Self {
+        points.into_iter().fold(
+            Bounds {
+                min: (0, 0),
+                max: (0, 0),
+            },
+            |b, &(x, y)| Bounds {
+                min: (b.min.0.min(x), b.min.1.min(y)),
+                max: (b.max.0.max(x), b.max.1.max(y)),
+            },
+        )
+    }
 
-            set.insert(tails[8]);
+    fn render<F: Fn(Pos) -> char>(&self, cell: F) -> String {
+        let mut out = String::new();
+        for y in self.min.1..=self.max.1 {
+            out.extend((self.min.0..=self.max.0).map(|x| cell((x, y))));
+            out.push('\n');
         }
+        out
     }
+}
 
-    for y in -15..6 {
-        for x in -11..15 {
-            if (x, y) == h {
-                print!("H")
-            } else if let Some((index, _)) = tails.iter().enumerate().find(|&(_, &p)| (x, y) == p) {
-                print!("{}", index + 1)
-            } else {
-                print!(".")
-            }
+pub fn render_knots(knots: &[Pos], bounds: Bounds) -> String {
+    bounds.render(|p| match knots.iter().position(|&k| k == p) {
+        Some(0) => 'H',
+        Some(i) => std::char::from_digit(i as u32, 36).unwrap_or('#'),
+        None if p == (0, 0) => 's',
+        None => '.',
+    })
+}
+
+pub fn render_trail(visited: &HashSet<Pos>) -> String {
+    Bounds::around(visited).render(|p| {
+        if p == (0, 0) {
+            's'
+        } else if visited.contains(&p) {
+            '#'
+        } else {
+            '.'
         }
-        println!()
-    }
+    })
+}
+
+// One frame per step, all drawn with the same bounds
+pub fn render_frames(input: &str, knot_count: usize) -> Vec<String> {
+    let frames: Vec<_> = replay(input, knot_count).collect();
+    let bounds = Bounds::around(frames.iter().flatten());
+
+    frames
+        .iter()
+        .map(|knots| render_knots(knots, bounds))
+        .collect()
+}
 
-    set.len()
+pub fn solve_1(input: &str) -> usize {
+    simulate(input, 2).tail_visited().len()
+}
+
+pub fn solve_2(input: &str) -> usize {
+    simulate(input, 10).tail_visited().len()
 }
 
 #[cfg(test)]
@@ -183,4 +267,51 @@ U 20";
 
         assert_eq!(p1, 2557);
     }
+
+    #[test]
+    fn tracks_every_knot() {
+        let rope = simulate("R 4\nU 4\nL 3\nD 1\nR 4\nD 1\nL 5\nR 2", 10);
+
+        assert_eq!(rope.knots()[0], (2, -2));
+        assert_eq!(rope.visited()[1].len(), 13);
+        assert_eq!(rope.tail_visited().len(), 1);
+        assert_eq!(rope.visited().len(), 10);
+    }
+
+    #[test]
+    fn supports_diagonal_moves() {
+        let rope = simulate("UR 3\nDL 1", 3);
+
+        assert_eq!(rope.knots(), &[(2, -2), (2, -2), (1, -1)]);
+        assert_eq!(rope.visited()[1], HashSet::from([(0, 0), (1, -1), (2, -2)]));
+    }
+
+    #[test]
+    fn replays_every_step() {
+        let steps: Vec<_> = replay("R 2\nU 1", 2).collect();
+
+        assert_eq!(
+            steps,
+            vec![
+                vec![(1, 0), (0, 0)],
+                vec![(2, 0), (1, 0)],
+                vec![(2, -1), (1, 0)]
+            ]
+        );
+    }
+
+    #[test]
+    fn renders_trail_and_frames() {
+        let simple = "R 4\nU 4\nL 3\nD 1\nR 4\nD 1\nL 5\nR 2";
+
+        assert_eq!(
+            render_trail(simulate(simple, 2).tail_visited()),
+            "..##.\n...##\n.####\n....#\ns###.\n"
+        );
+
+        let frames = render_frames(simple, 2);
+        assert_eq!(frames.len(), 24);
+        assert_eq!(frames[0], "......\n......\n......\n......\n1H....\n");
+        assert_eq!(frames[23], "......\n......\n.1H...\n......\ns.....\n");
+    }
 }
@@ -1,101 +1,499 @@
-use crate::day10::Instruction::{Addx, Noop};
+use std::collections::HashMap;
+use std::fmt::{self, Write};
+use std::iter;
 
-pub enum Instruction {
-    Noop,
-    Addx(i32),
-}
+use crate::common::{next_line, parse_int, starts_with};
+
+#[cfg(feature = "embedded-inputs")]
+pub const EMBEDDED: isize = solve_1_const(include_str!("./input"));
 
 const NOOP_CYCLES: usize = 1;
 const ADDX_CYCLES: usize = 2;
 const MAX_COUNT: usize = 240;
 
-fn parse(input: &str) -> Vec<Instruction> {
-    input
-        .lines()
-        .map(|l| {
-            if l.len() > 4 {
-                let (_, arg) = l.split_once(' ').unwrap();
-
-                Addx(arg.trim().parse().unwrap())
-            } else {
-                Noop
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Registers {
+    pub x: isize,
+}
+
+impl Default for Registers {
+    fn default() -> Self {
+        Registers { x: 1 }
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct OpSpec {
+    pub mnemonic: &'static str,
+    pub cycles: usize,
+    pub operands: usize,
+    // Applied at the end of the instruction's last cycle
+    pub effect: fn(&mut Registers, &[isize]),
+}
+
+pub const NOOP: usize = 0;
+pub const ADDX: usize = 1;
+
+pub const INSTRUCTION_SET: [OpSpec; 2] = [
+    OpSpec {
+        mnemonic: "noop",
+        cycles: NOOP_CYCLES,
+        operands: 0,
+        effect: |_, _| {},
+    },
+    OpSpec {
+        mnemonic: "addx",
+        cycles: ADDX_CYCLES,
+        operands: 1,
+        effect: |registers, args| registers.x += args[0],
+    },
+];
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Instruction {
+    // Index into the instruction set
+    pub opcode: usize,
+    pub args: Vec<isize>,
+}
+
+impl Instruction {
+    pub fn noop() -> Self {
+        Instruction {
+            opcode: NOOP,
+            args: vec![],
+        }
+    }
+
+    pub fn addx(value: isize) -> Self {
+        Instruction {
+            opcode: ADDX,
+            args: vec![value],
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum AsmError {
+    UnknownMnemonic {
+        line: usize,
+        mnemonic: String,
+    },
+    WrongOperandCount {
+        line: usize,
+        mnemonic: String,
+        expected: usize,
+        found: usize,
+    },
+    InvalidOperand {
+        line: usize,
+        operand: String,
+    },
+    DuplicateLabel {
+        line: usize,
+        label: String,
+    },
+}
+
+// Splits a source line into an optional label and the instruction words, dropping comments
+fn tokenize(line: &str) -> (Option<&str>, Vec<&str>) {
+    let code = line.split(';').next().unwrap_or("").trim();
+
+    match code.split_once(':') {
+        Some((label, rest)) => (Some(label.trim()), rest.split_whitespace().collect()),
+        None => (None, code.split_whitespace().collect()),
+    }
+}
+
+pub fn assemble(source: &str) -> Result<Vec<Instruction>, AsmError> {
+    assemble_with(source, &INSTRUCTION_SET)
+}
+
+// Labels can be used as operands and stand for the index of the instruction that follows them
+pub fn assemble_with(
+    source: &str,
+    instruction_set: &[OpSpec],
+) -> Result<Vec<Instruction>, AsmError> {
+    let mut labels = HashMap::new();
+    let mut index = 0;
+
+    for (line, text) in source.lines().enumerate() {
+        let (label, words) = tokenize(text);
+
+        if let Some(label) = label {
+            if labels.insert(label, index).is_some() {
+                return Err(AsmError::DuplicateLabel {
+                    line: line + 1,
+                    label: label.to_string(),
+                });
             }
-        })
-        .collect()
+        }
+        if !words.is_empty() {
+            index += 1;
+        }
+    }
+
+    let mut program = vec![];
+
+    for (line, text) in source.lines().enumerate() {
+        let line = line + 1;
+        let (_, words) = tokenize(text);
+        let Some((&mnemonic, operands)) = words.split_first() else {
+            continue;
+        };
+
+        let opcode = instruction_set
+            .iter()
+            .position(|spec| spec.mnemonic == mnemonic)
+            .ok_or_else(|| AsmError::UnknownMnemonic {
+                line,
+                mnemonic: mnemonic.to_string(),
+            })?;
+
+        let expected = instruction_set[opcode].operands;
+        if operands.len() != expected {
+            return Err(AsmError::WrongOperandCount {
+                line,
+                mnemonic: mnemonic.to_string(),
+                expected,
+                found: operands.len(),
+            });
+        }
+
+        let args = operands
+            .iter()
+            .map(|&operand| {
+                operand
+                    .parse()
+                    .ok()
+                    .or_else(|| labels.get(operand).map(|&i| i as isize))
+                    .ok_or_else(|| AsmError::InvalidOperand {
+                        line,
+                        operand: operand.to_string(),
+                    })
+            })
+            .collect::<Result<_, _>>()?;
+
+        program.push(Instruction { opcode, args });
+    }
+
+    Ok(program)
 }
 
-pub fn run_program(instrs: Vec<Instruction>) -> [isize; MAX_COUNT] {
-    let mut array = [0; MAX_COUNT];
+// Canonical source, annotated with the cycles each instruction runs in
+pub fn disassemble(program: &[Instruction], instruction_set: &[OpSpec]) -> String {
+    let mut out = String::new();
+    let mut cycle = 1;
 
-    let mut x: isize = 1;
+    for instr in program {
+        let spec = &instruction_set[instr.opcode];
+        let text = iter::once(spec.mnemonic.to_string())
+            .chain(instr.args.iter().map(|a| a.to_string()))
+            .collect::<Vec<_>>()
+            .join(" ");
 
-    let mut next_value = 0;
+        let cycles = match spec.cycles {
+            0 => format!("no cycles, before {}", cycle),
+            1 => format!("cycle {}", cycle),
+            n => format!("cycles {}-{}", cycle, cycle + n - 1),
+        };
+        writeln!(out, "{:<12}; {}", text, cycles).unwrap();
 
-    let mut skip_cycles: usize = 0;
+        cycle += spec.cycles;
+    }
+
+    out
+}
+
+fn parse(input: &str) -> Vec<Instruction> {
+    assemble(input).expect("Invalid program")
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct CycleState {
+    // Starts at 1, like in the puzzle
+    pub cycle: usize,
+    // The register values during the cycle
+    pub registers: Registers,
+}
+
+pub enum Breakpoint {
+    Cycle(usize),
+    X(isize),
+    When(Box<dyn Fn(&CycleState) -> bool>),
+}
+
+impl Breakpoint {
+    fn matches(&self, state: &CycleState) -> bool {
+        match self {
+            Breakpoint::Cycle(cycle) => state.cycle == *cycle,
+            Breakpoint::X(x) => state.registers.x == *x,
+            Breakpoint::When(condition) => condition(state),
+        }
+    }
+}
 
-    let mut moves = instrs.iter();
+pub struct Cpu {
+    instruction_set: Vec<OpSpec>,
+    program: Vec<Instruction>,
+    pc: usize,
+    registers: Registers,
+    cycle: usize,
+    cycles_left: usize,
+    breakpoints: Vec<Breakpoint>,
+}
+
+impl Cpu {
+    pub fn new(program: Vec<Instruction>) -> Self {
+        Cpu::with_instruction_set(program, INSTRUCTION_SET.to_vec())
+    }
 
-    for cycle_count in 0..MAX_COUNT {
-        if skip_cycles > 0 {
-            skip_cycles -= 1;
+    pub fn with_instruction_set(program: Vec<Instruction>, instruction_set: Vec<OpSpec>) -> Self {
+        for instr in &program {
+            let spec = instruction_set
+                .get(instr.opcode)
+                .unwrap_or_else(|| panic!("Unknown opcode {}", instr.opcode));
+            assert_eq!(
+                spec.operands,
+                instr.args.len(),
+                "Wrong number of operands for {}",
+                spec.mnemonic
+            );
         }
 
-        if skip_cycles == 0 {
-            x += next_value;
-            next_value = 0;
+        Cpu {
+            instruction_set,
+            program,
+            pc: 0,
+            registers: Registers::default(),
+            cycle: 0,
+            cycles_left: 0,
+            breakpoints: vec![],
         }
+    }
 
-        array[cycle_count] = x;
+    pub fn registers(&self) -> Registers {
+        self.registers
+    }
 
-        if skip_cycles == 0 {
-            if let Some(instr) = moves.next() {
-                match instr {
-                    Addx(value) => {
-                        next_value = *value as isize;
-                        skip_cycles = ADDX_CYCLES;
-                    }
-                    Noop => {
-                        skip_cycles = NOOP_CYCLES;
-                    }
-                }
+    pub fn cycle(&self) -> usize {
+        self.cycle
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.cycles_left == 0 && self.pc >= self.program.len()
+    }
+
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) {
+        self.breakpoints.push(breakpoint);
+    }
+
+    fn execute(&mut self) {
+        let instr = &self.program[self.pc];
+        (self.instruction_set[instr.opcode].effect)(&mut self.registers, &instr.args);
+        self.pc += 1;
+    }
+
+    // Runs until a cycle matching any breakpoint, or `None` once the program is done
+    pub fn run_until_break(&mut self) -> Option<CycleState> {
+        while let Some(state) = self.next() {
+            if self.breakpoints.iter().any(|b| b.matches(&state)) {
+                return Some(state);
             }
         }
+        None
     }
+}
+
+impl Iterator for Cpu {
+    type Item = CycleState;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.cycles_left == 0 {
+            let instr = self.program.get(self.pc)?;
+            self.cycles_left = self.instruction_set[instr.opcode].cycles;
+
+            if self.cycles_left == 0 {
+                self.execute();
+            }
+        }
+
+        self.cycle += 1;
+        let state = CycleState {
+            cycle: self.cycle,
+            registers: self.registers,
+        };
 
-    return array;
+        self.cycles_left -= 1;
+        if self.cycles_left == 0 {
+            self.execute();
+        }
+
+        Some(state)
+    }
 }
 
-pub fn solve_1(input: &str) -> isize {
-    let moves = parse(input);
+// The value of X during every cycle of the program, and the registers once it has halted
+pub fn run_program(instrs: Vec<Instruction>) -> (Vec<isize>, Registers) {
+    let mut cpu = Cpu::new(instrs);
+    let states = cpu.by_ref().map(|state| state.registers.x).collect();
 
-    let array = run_program(moves);
+    (states, cpu.registers())
+}
 
-    array[19] * 20
-        + array[59] * 60
-        + array[99] * 100
-        + array[139] * 140
-        + array[179] * 180
-        + array[219] * 220
+// The value of X during the given cycle; after the program ends, X keeps its final value
+fn x_during(states: &[isize], after: Registers, cycle: usize) -> isize {
+    states.get(cycle - 1).copied().unwrap_or(after.x)
 }
 
-pub fn solve_2(input: &str) {
+pub fn solve_1(input: &str) -> isize {
     let moves = parse(input);
 
-    let states = run_program(moves);
+    let (array, after) = run_program(moves);
 
-    for (cycle, &x) in states.iter().enumerate() {
-        let line_pos = (cycle % 40) as isize;
-        if line_pos == 0 {
-            println!()
+    (20..=220)
+        .step_by(40)
+        .map(|cycle| x_during(&array, after, cycle) * cycle as isize)
+        .sum()
+}
+
+pub const fn solve_1_const(input: &str) -> isize {
+    let bytes = input.as_bytes();
+    let mut x: isize = 1;
+    let mut cycle: isize = 0;
+    let mut strength = 0;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        // Blank lines are skipped, like the assembler does
+        if bytes[i] == b'\n' || bytes[i] == b'\r' {
+            i = next_line(bytes, i);
+            continue;
         }
-        if line_pos - 1 <= x && x <= line_pos + 1 {
-            print!("█");
+
+        let (cycles, delta, end) = if starts_with(bytes, i, b"addx ") {
+            let (value, end) = parse_int(bytes, i + 5);
+            (ADDX_CYCLES, value as isize, end)
+        } else if starts_with(bytes, i, b"noop") {
+            (NOOP_CYCLES, 0, i + 4)
         } else {
-            print!(" ");
+            panic!("Unknown instruction");
+        };
+
+        let mut c = 0;
+        while c < cycles {
+            cycle += 1;
+            if cycle <= MAX_COUNT as isize && cycle % 40 == 20 {
+                strength += cycle * x;
+            }
+            c += 1;
+        }
+
+        x += delta;
+        i = next_line(bytes, end);
+    }
+
+    // Short programs leave X at its final value for the remaining sampled cycles
+    while cycle < 220 {
+        cycle += 1;
+        if cycle % 40 == 20 {
+            strength += cycle * x;
+        }
+    }
+
+    strength
+}
+
+const SCREEN_WIDTH: usize = 40;
+const GLYPH_WIDTH: usize = 5;
+
+const GLYPHS: [(char, &str); 17] = [
+    ('A', ".##.#..##..######..##..#"),
+    ('B', "###.#..####.#..##..####."),
+    ('C', ".##.#..##...#...#..#.##."),
+    ('E', "#####...###.#...#...####"),
+    ('F', "#####...###.#...#...#..."),
+    ('G', ".##.#..##...#.###..#.###"),
+    ('H', "#..##..######..##..##..#"),
+    ('I', ".###..#...#...#...#..###"),
+    ('J', "..##...#...#...##..#.##."),
+    ('K', "#..##.#.##..#.#.#.#.#..#"),
+    ('L', "#...#...#...#...#...####"),
+    ('O', ".##.#..##..##..##..#.##."),
+    ('P', "###.#..##..####.#...#..."),
+    ('R', "###.#..##..####.#.#.#..#"),
+    ('S', ".####...#....##....####."),
+    ('U', "#..##..##..##..##..#.##."),
+    ('Z', "####...#..#..#..#...####"),
+];
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Screen {
+    pub pixels: Vec<Vec<bool>>,
+}
+
+impl Screen {
+    pub fn draw(states: &[isize], after: Registers, cycles: usize) -> Self {
+        let pixels = (0..cycles)
+            .collect::<Vec<_>>()
+            .chunks(SCREEN_WIDTH)
+            .map(|row| {
+                row.iter()
+                    .map(|&cycle| {
+                        let x = x_during(states, after, cycle + 1);
+                        let line_pos = (cycle % SCREEN_WIDTH) as isize;
+                        line_pos - 1 <= x && x <= line_pos + 1
+                    })
+                    .collect()
+            })
+            .collect();
+
+        Screen { pixels }
+    }
+
+    // Letters that aren't in the glyph table come out as '?'
+    pub fn read_letters(&self) -> String {
+        let width = self.pixels.first().map_or(0, |row| row.len());
+
+        (0..width / GLYPH_WIDTH)
+            .map(|letter| {
+                let glyph: String = self
+                    .pixels
+                    .iter()
+                    .flat_map(|row| &row[letter * GLYPH_WIDTH..letter * GLYPH_WIDTH + 4])
+                    .map(|&lit| if lit { '#' } else { '.' })
+                    .collect();
+
+                GLYPHS
+                    .iter()
+                    .find(|(_, pattern)| *pattern == glyph)
+                    .map_or('?', |&(c, _)| c)
+            })
+            .collect()
+    }
+}
+
+impl fmt::Display for Screen {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for row in &self.pixels {
+            let line: String = row.iter().map(|&lit| if lit { '#' } else { '.' }).collect();
+            writeln!(f, "{}", line)?;
         }
+        Ok(())
     }
 }
 
+pub fn crt_image(input: &str) -> Screen {
+    let moves = parse(input);
+
+    let (states, after) = run_program(moves);
+
+    Screen::draw(&states, after, MAX_COUNT)
+}
+
+pub fn solve_2(input: &str) -> String {
+    crt_image(input).read_letters()
+}
+
 #[cfg(test)]
 mod tests {
     use crate::common::read_file_to_string;
@@ -251,7 +649,18 @@ addx -11
 noop
 noop
 noop";
-        solve_2(simple);
+        let image = crt_image(simple).to_string();
+
+        assert_eq!(
+            image,
+            r"##..##..##..##..##..##..##..##..##..##..
+###...###...###...###...###...###...###.
+####....####....####....####....####....
+#####.....#####.....#####.....#####.....
+######......######......######......####
+#######.......#######.......#######.....
+"
+        );
     }
 
     #[test]
@@ -274,8 +683,220 @@ noop";
     #[test]
     fn it_works_2() {
         let lines = read_file_to_string("src/day10/input");
-        solve_2(&lines.trim_end());
+        let p2 = solve_2(&lines.trim_end());
+
+        assert_eq!(p2, "PBZGRAZA");
+    }
+
+    #[test]
+    fn it_works_const() {
+        let lines = read_file_to_string("src/day10/input");
+
+        assert_eq!(solve_1_const(&lines), solve_1(lines.trim_end()));
+        assert_eq!(solve_1_const("addx 3"), 2880);
+        assert_eq!(
+            solve_1_const("noop\n\naddx 3\naddx -5\n"),
+            solve_1_const("noop\naddx 3\naddx -5")
+        );
+        assert_eq!(
+            solve_1_const("noop\r\n\r\naddx 3\r\naddx -5\r\n"),
+            solve_1("noop\naddx 3\naddx -5")
+        );
+        assert_eq!(
+            solve_1_const("noop\naddx 3\naddx -5"),
+            solve_1("noop\naddx 3\naddx -5")
+        );
+    }
+
+    #[cfg(feature = "embedded-inputs")]
+    #[test]
+    fn embedded_matches_runtime() {
+        let lines = read_file_to_string("src/day10/input");
+
+        assert_eq!(EMBEDDED, solve_1(lines.trim_end()));
+    }
+
+    #[test]
+    fn emulates_small_program() {
+        let states: Vec<_> = Cpu::new(parse("noop\naddx 3\naddx -5")).collect();
+
+        assert_eq!(
+            states.iter().map(|s| s.registers.x).collect::<Vec<_>>(),
+            vec![1, 1, 1, 4, 4]
+        );
+        assert_eq!(states.last().unwrap().cycle, 5);
+
+        let mut cpu = Cpu::new(parse("noop\naddx 3\naddx -5"));
+        cpu.by_ref().for_each(drop);
+        assert!(cpu.is_halted());
+        assert_eq!(cpu.registers().x, -1);
+    }
+
+    #[test]
+    fn short_program_keeps_final_x() {
+        // X is 4 once `addx 3` is done, for every remaining sampled cycle
+        assert_eq!(solve_1("addx 3"), 2880);
+        let screen = crt_image("addx 3");
+        assert!(screen.pixels[5][4]);
+        assert!(!screen.pixels[5][39]);
+    }
+
+    #[test]
+    fn runs_past_240_cycles() {
+        let program = vec![Instruction::addx(1); 200];
+
+        let (states, after) = run_program(program);
+
+        assert_eq!(states.len(), 400);
+        assert_eq!(states[399], 200);
+        assert_eq!(after.x, 201);
+    }
+
+    #[test]
+    fn stops_at_breakpoints() {
+        let lines = read_file_to_string("src/day10/input");
+        let mut cpu = Cpu::new(parse(lines.trim_end()));
+        cpu.add_breakpoint(Breakpoint::Cycle(220));
+
+        let state = cpu.run_until_break().unwrap();
+        assert_eq!(state.cycle, 220);
+        assert_eq!(
+            state.registers.x,
+            run_program(parse(lines.trim_end())).0[219]
+        );
+
+        let mut cpu = Cpu::new(parse("addx 5\naddx 2\nnoop"));
+        cpu.add_breakpoint(Breakpoint::X(8));
+        cpu.add_breakpoint(Breakpoint::When(Box::new(|s| s.registers.x > 1)));
+
+        assert_eq!(cpu.run_until_break().map(|s| s.cycle), Some(3));
+        assert_eq!(cpu.run_until_break().map(|s| s.cycle), Some(4));
+        assert_eq!(
+            cpu.run_until_break(),
+            Some(CycleState {
+                cycle: 5,
+                registers: Registers { x: 8 }
+            })
+        );
+        assert_eq!(cpu.run_until_break(), None);
+    }
+
+    #[test]
+    fn supports_custom_instructions() {
+        let mut set = INSTRUCTION_SET.to_vec();
+        set.push(OpSpec {
+            mnemonic: "mulx",
+            cycles: 3,
+            operands: 1,
+            effect: |registers, args| registers.x *= args[0],
+        });
+        set.push(OpSpec {
+            mnemonic: "setx",
+            cycles: 0,
+            operands: 1,
+            effect: |registers, args| registers.x = args[0],
+        });
+
+        let program = vec![
+            Instruction {
+                opcode: 3,
+                args: vec![7],
+            },
+            Instruction {
+                opcode: 2,
+                args: vec![3],
+            },
+            Instruction::addx(1),
+        ];
+
+        let xs: Vec<_> = Cpu::with_instruction_set(program, set)
+            .map(|s| s.registers.x)
+            .collect();
+
+        assert_eq!(xs, vec![7, 7, 7, 21, 21]);
+    }
+
+    #[test]
+    fn reads_letters() {
+        let screen = Screen {
+            pixels: GLYPHS[..8]
+                .iter()
+                .fold(vec![vec![]; 6], |mut rows, (_, pattern)| {
+                    for (row, chunk) in rows.iter_mut().zip(pattern.as_bytes().chunks(4)) {
+                        row.extend(chunk.iter().map(|&b| b == b'#'));
+                        row.push(false);
+                    }
+                    rows
+                }),
+        };
+
+        assert_eq!(screen.read_letters(), "ABCEFGHI");
+        assert!(GLYPHS.iter().all(|(_, pattern)| pattern.len() == 24));
+    }
+
+    #[test]
+    fn assembles_with_comments_and_labels() {
+        let source = r"; warm up
+start: noop
+  addx 15   ; add
+end:
+addx end
+";
+
+        assert_eq!(
+            assemble(source),
+            Ok(vec![
+                Instruction::noop(),
+                Instruction::addx(15),
+                Instruction::addx(2)
+            ])
+        );
+    }
+
+    #[test]
+    fn reports_assembly_errors() {
+        assert_eq!(
+            assemble("noop\nnop"),
+            Err(AsmError::UnknownMnemonic {
+                line: 2,
+                mnemonic: String::from("nop")
+            })
+        );
+        assert_eq!(
+            assemble("addx"),
+            Err(AsmError::WrongOperandCount {
+                line: 1,
+                mnemonic: String::from("addx"),
+                expected: 1,
+                found: 0
+            })
+        );
+        assert_eq!(
+            assemble("addx nowhere"),
+            Err(AsmError::InvalidOperand {
+                line: 1,
+                operand: String::from("nowhere")
+            })
+        );
+        assert_eq!(
+            assemble("a: noop\na: noop"),
+            Err(AsmError::DuplicateLabel {
+                line: 2,
+                label: String::from("a")
+            })
+        );
+    }
+
+    #[test]
+    fn disassembles_with_cycles() {
+        let program = assemble("noop\naddx 3\naddx -5").unwrap();
+
+        let source = disassemble(&program, &INSTRUCTION_SET);
 
-        // assert_eq!(p2, 2557);
+        assert_eq!(
+            source,
+            "noop        ; cycle 1\naddx 3      ; cycles 2-3\naddx -5     ; cycles 4-5\n"
+        );
+        assert_eq!(assemble(&source), Ok(program));
     }
 }
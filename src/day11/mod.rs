@@ -1,15 +1,159 @@
-use crate::day11::Op::{AddNum, Square, TimesNum};
+use std::iter::Peekable;
+use std::str::Chars;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Expr {
+    Old,
+    Num(usize),
+    Add(Box<Expr>, Box<Expr>),
+    Sub(Box<Expr>, Box<Expr>),
+    Mul(Box<Expr>, Box<Expr>),
+    Div(Box<Expr>, Box<Expr>),
+}
 
-#[derive(Debug)]
-enum Op {
-    Square,
-    TimesNum(usize),
-    AddNum(usize),
+#[derive(Debug, PartialEq, Eq)]
+pub enum ExprError {
+    UnexpectedChar(char),
+    UnexpectedEnd,
+    NumberTooLarge(String),
+}
+
+impl Expr {
+    pub fn eval(&self, old: usize) -> usize {
+        match self {
+            Expr::Old => old,
+            Expr::Num(num) => *num,
+            Expr::Add(a, b) => a.eval(old) + b.eval(old),
+            // Worry levels can't go below zero, so subtraction stops there
+            Expr::Sub(a, b) => a.eval(old).saturating_sub(b.eval(old)),
+            Expr::Mul(a, b) => a.eval(old) * b.eval(old),
+            Expr::Div(a, b) => a
+                .eval(old)
+                .checked_div(b.eval(old))
+                .expect("Division by zero"),
+        }
+    }
+
+    // Division and subtraction (which stops at zero) don't survive reducing the worry
+    // level modulo something
+    pub fn is_ring_compatible(&self) -> bool {
+        match self {
+            Expr::Old | Expr::Num(_) => true,
+            Expr::Add(a, b) | Expr::Mul(a, b) => a.is_ring_compatible() && b.is_ring_compatible(),
+            Expr::Sub(_, _) | Expr::Div(_, _) => false,
+        }
+    }
+
+    // `old` reduced modulo `modulus`, or `None` if the expression isn't ring compatible
+    pub fn eval_mod(&self, old: usize, modulus: usize) -> Option<usize> {
+        let m = modulus as u128;
+
+        Some(match self {
+            Expr::Old => old % modulus,
+            Expr::Num(num) => num % modulus,
+            Expr::Add(a, b) => {
+                ((a.eval_mod(old, modulus)? as u128 + b.eval_mod(old, modulus)? as u128) % m)
+                    as usize
+            }
+            Expr::Mul(a, b) => {
+                ((a.eval_mod(old, modulus)? as u128 * b.eval_mod(old, modulus)? as u128) % m)
+                    as usize
+            }
+            Expr::Sub(_, _) | Expr::Div(_, _) => return None,
+        })
+    }
+
+    pub fn parse(input: &str) -> Result<Expr, ExprError> {
+        let mut chars = input.chars().peekable();
+        let expr = parse_sum(&mut chars)?;
+
+        match next_non_space(&mut chars) {
+            None => Ok(expr),
+            Some(c) => Err(ExprError::UnexpectedChar(c)),
+        }
+    }
+}
+
+fn next_non_space(chars: &mut Peekable<Chars>) -> Option<char> {
+    while chars.next_if(|c| c.is_whitespace()).is_some() {}
+    chars.next()
+}
+
+fn peek_non_space(chars: &mut Peekable<Chars>) -> Option<char> {
+    while chars.next_if(|c| c.is_whitespace()).is_some() {}
+    chars.peek().copied()
+}
+
+fn parse_sum(chars: &mut Peekable<Chars>) -> Result<Expr, ExprError> {
+    let mut expr = parse_product(chars)?;
+
+    while let Some(op @ ('+' | '-')) = peek_non_space(chars) {
+        chars.next();
+        let rhs = Box::new(parse_product(chars)?);
+        expr = if op == '+' {
+            Expr::Add(Box::new(expr), rhs)
+        } else {
+            Expr::Sub(Box::new(expr), rhs)
+        };
+    }
+
+    Ok(expr)
+}
+
+fn parse_product(chars: &mut Peekable<Chars>) -> Result<Expr, ExprError> {
+    let mut expr = parse_term(chars)?;
+
+    while let Some(op @ ('*' | '/')) = peek_non_space(chars) {
+        chars.next();
+        let rhs = Box::new(parse_term(chars)?);
+        expr = if op == '*' {
+            Expr::Mul(Box::new(expr), rhs)
+        } else {
+            Expr::Div(Box::new(expr), rhs)
+        };
+    }
+
+    Ok(expr)
+}
+
+fn parse_term(chars: &mut Peekable<Chars>) -> Result<Expr, ExprError> {
+    match next_non_space(chars) {
+        Some('(') => {
+            let expr = parse_sum(chars)?;
+            match next_non_space(chars) {
+                Some(')') => Ok(expr),
+                Some(c) => Err(ExprError::UnexpectedChar(c)),
+                None => Err(ExprError::UnexpectedEnd),
+            }
+        }
+        Some(c) if c.is_ascii_digit() => {
+            let mut digits = String::from(c);
+            while let Some(d) = chars.next_if(|d| d.is_ascii_digit()) {
+                digits.push(d);
+            }
+            digits
+                .parse()
+                .map(Expr::Num)
+                .map_err(|_| ExprError::NumberTooLarge(digits))
+        }
+        Some('o') => {
+            for expected in ['l', 'd'] {
+                match chars.next() {
+                    Some(c) if c == expected => {}
+                    Some(c) => return Err(ExprError::UnexpectedChar(c)),
+                    None => return Err(ExprError::UnexpectedEnd),
+                }
+            }
+            Ok(Expr::Old)
+        }
+        Some(c) => Err(ExprError::UnexpectedChar(c)),
+        None => Err(ExprError::UnexpectedEnd),
+    }
 }
 
 #[derive(Debug)]
 struct Monkey {
-    operation: Op,
+    operation: Expr,
     test_divisible_by: usize,
     if_true_id: usize,
     if_false_id: usize,
@@ -17,11 +161,13 @@ struct Monkey {
 
 impl Monkey {
     fn perform_op(&self, old: &usize) -> usize {
-        match self.operation {
-            Square => old * old,
-            TimesNum(num) => old * num,
-            AddNum(num) => old + num,
-        }
+        self.operation.eval(*old)
+    }
+
+    fn perform_op_mod(&self, old: &usize, modulus: usize) -> usize {
+        self.operation
+            .eval_mod(*old, modulus)
+            .expect("Operation can't be used with modulo reduction")
     }
 }
 
@@ -91,55 +237,217 @@ fn parse(input: &str) -> (Vec<Monkey>, Vec<Vec<usize>>) {
     (monkeys, items)
 }
 
-fn parse_operation(line: &str) -> Op {
-    let mut words = line.split_once('=').unwrap().1.split_whitespace().skip(1);
-    let op = words.next().unwrap();
-    let operand = words.next().unwrap();
+fn parse_operation(line: &str) -> Expr {
+    let (_, expr) = line.split_once('=').unwrap();
+
+    Expr::parse(expr).unwrap()
+}
 
-    match (op, operand) {
-        ("*", "old") => Square,
-        ("+", num) => AddNum(num.parse().unwrap()),
-        ("*", num) => TimesNum(num.parse().unwrap()),
-        _ => unreachable!(),
+#[derive(Copy, Clone, Debug)]
+pub enum Relief {
+    DivideBy(usize),
+    // Keeps worry levels modulo the product of every monkey's divisor
+    Modulo,
+    Custom(fn(usize) -> usize),
+    Disabled,
+}
+
+impl Relief {
+    fn is_modulo(&self) -> bool {
+        matches!(self, Relief::Modulo)
     }
 }
 
-pub fn solve(input: &str, is_part_2: bool) -> u64 {
-    let (monkeys, mut items) = parse(input);
-    let mut inspections: Vec<u64> = monkeys.iter().map(|_| 0).collect();
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub rounds: usize,
+    pub relief: Relief,
+    pub top_k: usize,
+    pub record_items: bool,
+    pub record_throws: bool,
+    // Track every item modulo each monkey's divisor instead of its actual worry level
+    pub residues: bool,
+}
 
-    let rounds = if is_part_2 { 10_000 } else { 20 };
+impl Config {
+    pub fn part_1() -> Self {
+        Config {
+            rounds: 20,
+            relief: Relief::DivideBy(3),
+            top_k: 2,
+            record_items: false,
+            record_throws: false,
+            residues: false,
+        }
+    }
+
+    pub fn part_2() -> Self {
+        Config {
+            rounds: 10_000,
+            relief: Relief::Modulo,
+            ..Config::part_1()
+        }
+    }
+}
 
-    let modulo: usize = monkeys.iter().map(|m| m.test_divisible_by).product();
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Worry {
+    Level(usize),
+    // The worry level modulo the divisor of the monkey with the same index
+    Residues(Vec<usize>),
+}
 
-    println!("mod === {}", modulo);
+impl Worry {
+    fn inspect(&self, monkey: &Monkey, relief: Relief, modulo: usize, divisors: &[usize]) -> Worry {
+        match self {
+            Worry::Level(level) => Worry::Level(match relief {
+                Relief::DivideBy(n) => monkey.perform_op(level) / n,
+                Relief::Modulo => monkey.perform_op_mod(level, modulo),
+                Relief::Custom(f) => f(monkey.perform_op(level)),
+                Relief::Disabled => monkey.perform_op(level),
+            }),
+            Worry::Residues(residues) => Worry::Residues(
+                residues
+                    .iter()
+                    .zip(divisors)
+                    .map(|(r, &d)| monkey.perform_op_mod(r, d))
+                    .collect(),
+            ),
+        }
+    }
+
+    fn is_divisible(&self, monkey_index: usize, divisor: usize) -> bool {
+        match self {
+            Worry::Level(level) => level % divisor == 0,
+            Worry::Residues(residues) => residues[monkey_index] == 0,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Throw {
+    pub round: usize,
+    pub from: usize,
+    pub to: usize,
+    pub worry: Worry,
+}
+
+#[derive(Debug, Default)]
+pub struct SimulationResult {
+    // Inspections done by each monkey during each round
+    pub inspections: Vec<Vec<u64>>,
+    // Items held by each monkey after each round, if recorded
+    pub items: Vec<Vec<Vec<Worry>>>,
+    pub throws: Vec<Throw>,
+    pub monkey_business: u64,
+}
+
+impl SimulationResult {
+    pub fn total_inspections(&self) -> Vec<u64> {
+        self.inspections
+            .iter()
+            .fold(vec![], |mut total: Vec<u64>, round| {
+                total.resize(round.len(), 0);
+                total.iter_mut().zip(round).for_each(|(t, r)| *t += r);
+                total
+            })
+    }
+}
+
+pub fn simulate(input: &str, config: &Config) -> SimulationResult {
+    let (monkeys, items) = parse(input);
+    let mut result = SimulationResult::default();
+
+    let divisors: Vec<usize> = monkeys.iter().map(|m| m.test_divisible_by).collect();
+    let modulo: usize = if config.relief.is_modulo() && !config.residues {
+        divisors.iter().product()
+    } else {
+        0
+    };
+
+    if config.residues {
+        match config.relief {
+            Relief::Disabled | Relief::Modulo => {}
+            relief => panic!("Residues can't be combined with {:?} relief", relief),
+        }
+    }
+
+    let mut items: Vec<Vec<Worry>> = items
+        .into_iter()
+        .map(|held| {
+            held.into_iter()
+                .map(|level| {
+                    if config.residues {
+                        Worry::Residues(divisors.iter().map(|d| level % d).collect())
+                    } else {
+                        Worry::Level(level)
+                    }
+                })
+                .collect()
+        })
+        .collect();
+
+    if config.residues || config.relief.is_modulo() {
+        if let Some(index) = monkeys
+            .iter()
+            .position(|m| !m.operation.is_ring_compatible())
+        {
+            panic!(
+                "Monkey {} uses division or subtraction, which can't be combined with modulo reduction",
+                index
+            );
+        }
+    }
+
+    for round in 1..=config.rounds {
+        let mut inspections = vec![0; monkeys.len()];
 
-    for _ in 0..rounds {
         for (index, m) in monkeys.iter().enumerate() {
-            for mut item in items[index].clone() {
-                item = m.perform_op(&item);
+            for item in items[index].clone() {
                 inspections[index] += 1;
-                if !is_part_2 {
-                    item /= 3;
-                } else {
-                    item %= modulo;
-                }
+                let item = item.inspect(m, config.relief, modulo, &divisors);
 
-                let next_id = if item % m.test_divisible_by == 0 {
+                let next_id = if item.is_divisible(index, m.test_divisible_by) {
                     m.if_true_id
                 } else {
                     m.if_false_id
                 };
 
+                if config.record_throws {
+                    result.throws.push(Throw {
+                        round,
+                        from: index,
+                        to: next_id,
+                        worry: item.clone(),
+                    });
+                }
+
                 items[next_id].push(item);
             }
             items[index].clear();
         }
+
+        result.inspections.push(inspections);
+        if config.record_items {
+            result.items.push(items.clone());
+        }
     }
 
-    inspections.sort();
+    let mut totals = result.total_inspections();
+    totals.sort();
+    result.monkey_business = totals.iter().rev().take(config.top_k).product();
 
-    inspections.iter().rev().take(2).product()
+    result
+}
+
+pub fn solve(input: &str, is_part_2: bool) -> u64 {
+    let config = if is_part_2 {
+        Config::part_2()
+    } else {
+        Config::part_1()
+    };
+
+    simulate(input, &config).monkey_business
 }
 
 #[cfg(test)]
@@ -149,9 +457,7 @@ mod tests {
 
     use super::*;
 
-    #[test]
-    fn it_works_simple() {
-        let simple = r"Monkey 0:
+    const EXAMPLE: &str = r"Monkey 0:
   Starting items: 79, 98
   Operation: new = old * 19
   Test: divisible by 23
@@ -178,6 +484,10 @@ Monkey 3:
   Test: divisible by 17
     If true: throw to monkey 0
     If false: throw to monkey 1";
+
+    #[test]
+    fn it_works_simple() {
+        let simple = EXAMPLE;
         let p1 = solve(&simple, false);
 
         assert_eq!(p1, 10605);
@@ -207,4 +517,264 @@ Monkey 3:
 
         assert_eq!(res, 25935263541);
     }
+
+    #[test]
+    fn parses_expressions() {
+        use Expr::*;
+
+        let old = || Box::new(Old);
+        let num = |n| Box::new(Num(n));
+
+        assert_eq!(Expr::parse(" old * old"), Ok(Mul(old(), old())));
+        assert_eq!(Expr::parse("3 * old"), Ok(Mul(num(3), old())));
+        assert_eq!(
+            Expr::parse("old + 2 * (old - 3)"),
+            Ok(Add(
+                old(),
+                Box::new(Mul(num(2), Box::new(Sub(old(), num(3)))))
+            ))
+        );
+        assert_eq!(
+            Expr::parse("old - 1 - 2"),
+            Ok(Sub(Box::new(Sub(old(), num(1))), num(2)))
+        );
+        assert_eq!(Expr::parse("old +"), Err(ExprError::UnexpectedEnd));
+        assert_eq!(Expr::parse("(old"), Err(ExprError::UnexpectedEnd));
+        assert_eq!(Expr::parse("olf"), Err(ExprError::UnexpectedChar('f')));
+        assert_eq!(Expr::parse("old % 2"), Err(ExprError::UnexpectedChar('%')));
+    }
+
+    #[test]
+    fn evaluates_expressions() {
+        let expr = Expr::parse("(old + 4) * old - 10 / 3").unwrap();
+
+        assert_eq!(expr.eval(5), 42);
+        assert!(!expr.is_ring_compatible());
+        assert_eq!(expr.eval_mod(5, 7), None);
+
+        let expr = Expr::parse("old - 3").unwrap();
+        assert_eq!(expr.eval(5), 2);
+        assert_eq!(expr.eval(1), 0);
+        assert!(!expr.is_ring_compatible());
+        assert_eq!(expr.eval_mod(5, 7), None);
+
+        let expr = Expr::parse("old * old + 30 * old").unwrap();
+        assert!(expr.is_ring_compatible());
+        for old in 0..50 {
+            assert_eq!(expr.eval_mod(old, 13), Some(expr.eval(old) % 13));
+        }
+    }
+
+    const SUBTRACTING: &str = r"Monkey 0:
+  Starting items: 1, 2
+  Operation: new = old - 3
+  Test: divisible by 2
+    If true: throw to monkey 1
+    If false: throw to monkey 1
+
+Monkey 1:
+  Starting items: 7
+  Operation: new = old + 6
+  Test: divisible by 5
+    If true: throw to monkey 0
+    If false: throw to monkey 0";
+
+    #[test]
+    fn subtraction_stops_at_zero() {
+        let result = simulate(SUBTRACTING, &Config::part_1());
+
+        assert_eq!(result.total_inspections(), vec![59, 60]);
+        assert_eq!(solve(SUBTRACTING, false), 3540);
+    }
+
+    #[test]
+    #[should_panic(expected = "Monkey 0 uses division or subtraction")]
+    fn rejects_subtraction_with_modulo() {
+        solve(SUBTRACTING, true);
+    }
+
+    #[test]
+    #[should_panic(expected = "Monkey 0 uses division")]
+    fn rejects_division_with_modulo() {
+        let simple = r"Monkey 0:
+  Starting items: 79, 98
+  Operation: new = old / 2
+  Test: divisible by 23
+    If true: throw to monkey 1
+    If false: throw to monkey 1
+
+Monkey 1:
+  Starting items: 54
+  Operation: new = old + 6
+  Test: divisible by 19
+    If true: throw to monkey 0
+    If false: throw to monkey 0";
+
+        solve(simple, true);
+    }
+
+    #[test]
+    fn records_round_statistics() {
+        let config = Config {
+            record_items: true,
+            record_throws: true,
+            ..Config::part_1()
+        };
+
+        let result = simulate(EXAMPLE, &config);
+
+        assert_eq!(result.inspections.len(), 20);
+        assert_eq!(result.inspections[0], vec![2, 4, 3, 5]);
+        assert_eq!(result.total_inspections(), vec![101, 95, 7, 105]);
+        assert_eq!(
+            result.items[0],
+            vec![
+                vec![20, 23, 27, 26],
+                vec![2080, 25, 167, 207, 401, 1046],
+                vec![],
+                vec![]
+            ]
+            .into_iter()
+            .map(|held| held.into_iter().map(Worry::Level).collect::<Vec<_>>())
+            .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            result.throws[0],
+            Throw {
+                round: 1,
+                from: 0,
+                to: 3,
+                worry: Worry::Level(500)
+            }
+        );
+        assert_eq!(
+            result.throws.len() as u64,
+            result.total_inspections().iter().sum()
+        );
+        assert_eq!(result.monkey_business, 10605);
+    }
+
+    #[test]
+    fn supports_custom_relief_and_top_k() {
+        let config = Config {
+            rounds: 10_000,
+            relief: Relief::Modulo,
+            top_k: 3,
+            ..Config::part_1()
+        };
+        assert_eq!(
+            simulate(EXAMPLE, &config).monkey_business,
+            52166 * 52013 * 47830
+        );
+
+        let config = Config {
+            relief: Relief::Custom(|worry| worry / 3),
+            ..Config::part_1()
+        };
+        assert_eq!(simulate(EXAMPLE, &config).monkey_business, 10605);
+
+        let config = Config {
+            rounds: 1,
+            relief: Relief::Disabled,
+            ..Config::part_2()
+        };
+        assert_eq!(simulate(EXAMPLE, &config).inspections[0], vec![2, 4, 3, 6]);
+    }
+
+    #[test]
+    fn residues_match_modulo_reduction() {
+        let lines = read_file_to_string("src/day11/input");
+        let config = Config {
+            residues: true,
+            ..Config::part_2()
+        };
+
+        assert_eq!(
+            simulate(lines.trim_end(), &config).monkey_business,
+            25935263541
+        );
+    }
+
+    #[test]
+    fn residues_stay_exact_without_relief() {
+        // Non-coprime divisors, and worry levels that outgrow a u64 within a few dozen rounds
+        let input = r"Monkey 0:
+  Starting items: 7, 12
+  Operation: new = old * 3 + 2
+  Test: divisible by 4
+    If true: throw to monkey 1
+    If false: throw to monkey 2
+
+Monkey 1:
+  Starting items: 3
+  Operation: new = old * 5 + 1
+  Test: divisible by 6
+    If true: throw to monkey 2
+    If false: throw to monkey 0
+
+Monkey 2:
+  Starting items: 10
+  Operation: new = old + 7
+  Test: divisible by 10
+    If true: throw to monkey 0
+    If false: throw to monkey 1";
+
+        let rounds = 50;
+        let config = Config {
+            rounds,
+            relief: Relief::Disabled,
+            residues: true,
+            record_throws: true,
+            ..Config::part_1()
+        };
+        let result = simulate(input, &config);
+
+        // The same simulation with exact worry levels
+        let ops: [fn(u128) -> u128; 3] = [|old| old * 3 + 2, |old| old * 5 + 1, |old| old + 7];
+        let divisors = [4, 6, 10];
+        let targets = [(1, 2), (2, 0), (0, 1)];
+        let mut items: Vec<Vec<u128>> = vec![vec![7, 12], vec![3], vec![10]];
+        let mut inspections = vec![0; 3];
+        let mut throws = vec![];
+
+        for _ in 0..rounds {
+            for monkey in 0..3 {
+                for old in std::mem::take(&mut items[monkey]) {
+                    let level = ops[monkey](old);
+                    let to = if level.is_multiple_of(divisors[monkey]) {
+                        targets[monkey].0
+                    } else {
+                        targets[monkey].1
+                    };
+
+                    inspections[monkey] += 1;
+                    throws.push((monkey, to, level));
+                    items[to].push(level);
+                }
+            }
+        }
+
+        assert!(throws.iter().any(|&(_, _, level)| level > u64::MAX as u128));
+        assert_eq!(result.total_inspections(), inspections);
+        assert_eq!(result.throws.len(), throws.len());
+
+        for (throw, &(from, to, level)) in result.throws.iter().zip(&throws) {
+            assert_eq!((throw.from, throw.to), (from, to));
+            assert_eq!(
+                throw.worry,
+                Worry::Residues(divisors.iter().map(|&d| (level % d) as usize).collect())
+            );
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Residues can't be combined")]
+    fn rejects_residues_with_division_relief() {
+        let config = Config {
+            residues: true,
+            ..Config::part_1()
+        };
+
+        simulate(EXAMPLE, &config);
+    }
 }
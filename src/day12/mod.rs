@@ -1,11 +1,12 @@
 use std::cmp::Ordering;
-use std::collections::{BinaryHeap, HashMap};
+use std::collections::{BinaryHeap, VecDeque};
+use std::iter;
 
 type Position = (usize, usize);
 
 #[derive(Copy, Clone, Eq, PartialEq)]
 struct State {
-    cost: usize,
+    cost: u32,
     position: Position,
 }
 
@@ -27,17 +28,59 @@ impl Ord for State {
     }
 }
 
+// What a climber may do in a single step, and what it costs them
+#[derive(Copy, Clone)]
+pub struct ClimbingRules {
+    pub max_ascent: usize,
+    pub max_descent: usize,
+    // Cost of a step given the change in height, positive when climbing
+    pub step_cost: fn(isize) -> u32,
+}
+
+impl ClimbingRules {
+    // The puzzle's rules: at most one step up, any drop, every step costs the same
+    pub const STANDARD: ClimbingRules = ClimbingRules {
+        max_ascent: 1,
+        max_descent: usize::MAX,
+        step_cost: |_| 1,
+    };
+
+    pub fn allows(&self, from: usize, to: usize) -> bool {
+        if to >= from {
+            to - from <= self.max_ascent
+        } else {
+            from - to <= self.max_descent
+        }
+    }
+
+    pub fn cost(&self, from: usize, to: usize) -> u32 {
+        (self.step_cost)(to as isize - from as isize)
+    }
+}
+
 const POS_DELTAS: [(isize, isize); 4] = [(0, 1), (0, -1), (1, 0), (-1, 0)];
 
-fn get_neighs(adj_list: &[Vec<usize>], (x, y): Position) -> impl Iterator<Item = Position> + '_ {
+// With `backwards`, the positions we could have come from rather than the ones we can go to
+fn get_neighs<'a>(
+    adj_list: &'a [Vec<usize>],
+    (x, y): Position,
+    rules: &'a ClimbingRules,
+    backwards: bool,
+) -> impl Iterator<Item = Position> + 'a {
     let my_height = adj_list[y][x];
 
     POS_DELTAS.iter().filter_map(move |(dx, dy)| {
         let x = (x as isize + dx) as usize;
         let y = (y as isize + dy) as usize;
 
-        adj_list.get(y).and_then(|row| row.get(x)).and_then(|n| {
-            if &my_height - 1 <= *n {
+        adj_list.get(y).and_then(|row| row.get(x)).and_then(|&n| {
+            let allowed = if backwards {
+                rules.allows(n, my_height)
+            } else {
+                rules.allows(my_height, n)
+            };
+
+            if allowed {
                 Some((x, y))
             } else {
                 None
@@ -46,25 +89,102 @@ fn get_neighs(adj_list: &[Vec<usize>], (x, y): Position) -> impl Iterator<Item =
     })
 }
 
-fn shortest_path<F>(adj_list: &[Vec<usize>], start: Position, is_goal: F) -> Option<usize>
+const UNVISITED: u32 = u32::MAX;
+
+// Flat per-cell bookkeeping, indexed by `y * width + x`
+struct Cells {
+    width: usize,
+    dist: Vec<u32>,
+    prev: Vec<u32>,
+}
+
+impl Cells {
+    fn new(adj_list: &[Vec<usize>]) -> Self {
+        let width = adj_list.first().map_or(0, |row| row.len());
+        let len = width * adj_list.len();
+
+        Cells {
+            width,
+            dist: vec![UNVISITED; len],
+            prev: vec![UNVISITED; len],
+        }
+    }
+
+    fn index(&self, (x, y): Position) -> usize {
+        y * self.width + x
+    }
+
+    fn path_to(&self, end: Position) -> Vec<Position> {
+        let mut path: Vec<Position> = iter::successors(Some(end), |&p| {
+            let prev = self.prev[self.index(p)];
+            (prev != UNVISITED).then(|| (prev as usize % self.width, prev as usize / self.width))
+        })
+        .collect();
+        path.reverse();
+        path
+    }
+}
+
+// The positions visited from `start` up to and including the first goal reached
+fn shortest_path<F>(
+    adj_list: &[Vec<usize>],
+    start: Position,
+    is_goal: F,
+    rules: &ClimbingRules,
+    backwards: bool,
+) -> Option<Vec<Position>>
 where
     F: Fn(&Position) -> bool,
 {
-    // dist[node] = current shortest distance from `start` to `node`
-    let mut dist: HashMap<Position, usize> = adj_list
-        .iter()
-        .enumerate()
-        .flat_map(|(y, row)| {
-            row.iter()
-                .enumerate()
-                .map(move |(x, _)| ((x, y), usize::MAX))
-        })
-        .collect();
+    let mut cells = Cells::new(adj_list);
+    let mut queue = VecDeque::new();
+
+    let start_index = cells.index(start);
+    cells.dist[start_index] = 0;
+    queue.push_back(start);
+
+    // Every edge costs the same, so the first time we see a node is the shortest way there
+    while let Some(position) = queue.pop_front() {
+        if is_goal(&position) {
+            return Some(cells.path_to(position));
+        }
+
+        let index = cells.index(position);
+        let next_cost = cells.dist[index] + 1;
+
+        for pos in get_neighs(adj_list, position, rules, backwards) {
+            let next = cells.index(pos);
+
+            if cells.dist[next] == UNVISITED {
+                cells.dist[next] = next_cost;
+                cells.prev[next] = index as u32;
+                queue.push_back(pos);
+            }
+        }
+    }
+
+    // Goal not reachable
+    None
+}
+
+// Like `shortest_path`, but for moves that don't all cost the same
+fn cheapest_path<F>(
+    adj_list: &[Vec<usize>],
+    start: Position,
+    is_goal: F,
+    rules: &ClimbingRules,
+) -> Option<(usize, Vec<Position>)>
+where
+    F: Fn(&Position) -> bool,
+{
+    // cells.dist[node] = current shortest distance from `start` to `node`
+    let mut cells = Cells::new(adj_list);
 
     let mut heap = BinaryHeap::new();
 
     // We're at `start`, with a zero cost
-    dist.insert(start, 0);
+    let start_index = cells.index(start);
+    cells.dist[start_index] = 0;
     heap.push(State {
         position: start,
         cost: 0,
@@ -73,27 +193,37 @@ where
     // Examine the frontier with lower cost nodes first (min-heap)
     while let Some(State { position, cost }) = heap.pop() {
         if is_goal(&position) {
-            return Some(cost);
+            return Some((cost as usize, cells.path_to(position)));
         }
 
+        let index = cells.index(position);
+
         // Important as we may have already found a better way
-        if cost > dist[&position] {
+        if cost > cells.dist[index] {
             continue;
         }
 
         // For each node we can reach, see if we can find a way with
         // a lower cost going through this node
-        for pos in get_neighs(adj_list, position) {
-            let next_cost = cost + 1;
+        for pos in get_neighs(adj_list, position, rules, false) {
+            let step = rules.cost(adj_list[position.1][position.0], adj_list[pos.1][pos.0]);
+            let next = cells.index(pos);
+
+            // Costs that don't fit below the `UNVISITED` marker can't be tracked
+            let next_cost = match cost.checked_add(step) {
+                Some(next_cost) if next_cost != UNVISITED => next_cost,
+                _ => continue,
+            };
 
             // If so, add it to the frontier and continue
-            if next_cost < dist[&pos] {
+            if next_cost < cells.dist[next] {
                 heap.push(State {
                     position: pos,
                     cost: next_cost,
                 });
                 // Relaxation, we have now found a better way
-                dist.insert(pos, next_cost);
+                cells.dist[next] = next_cost;
+                cells.prev[next] = index as u32;
             }
         }
     }
@@ -132,32 +262,95 @@ fn parse(input: &str) -> (Vec<Vec<usize>>, Position, Position) {
     )
 }
 
-pub fn solve(input: &str) -> usize {
+// The climber's route from `S` to `E`
+pub fn climb(input: &str) -> Vec<Position> {
     let (grid, goal, start) = parse(input);
 
-    shortest_path(&grid, start, |pos| pos == &goal).unwrap()
+    let mut path = shortest_path(
+        &grid,
+        start,
+        |pos| pos == &goal,
+        &ClimbingRules::STANDARD,
+        true,
+    )
+    .unwrap();
+    path.reverse();
+    path
 }
 
-pub fn solve_2(input: &str) -> usize {
+// The climber's route to `E` from the closest square at elevation `a`
+pub fn climb_from_lowest(input: &str) -> Vec<Position> {
     let (grid, _start, goal) = parse(input);
 
-    shortest_path(&grid, goal, |&(x, y)| grid[y][x] == 'a' as usize).unwrap()
+    let mut path = shortest_path(
+        &grid,
+        goal,
+        |&(x, y)| grid[y][x] == 'a' as usize,
+        &ClimbingRules::STANDARD,
+        true,
+    )
+    .unwrap();
+    path.reverse();
+    path
+}
+
+// The cheapest route from `S` to `E` under `rules`, with its total cost
+pub fn cheapest_route(input: &str, rules: &ClimbingRules) -> Option<(usize, Vec<Position>)> {
+    let (grid, start, goal) = parse(input);
+
+    cheapest_path(&grid, start, |pos| pos == &goal, rules)
+}
+
+pub fn render_path(input: &str, path: &[Position]) -> String {
+    let mut canvas: Vec<Vec<char>> = input
+        .lines()
+        .map(|line| line.chars().map(|_| '.').collect())
+        .collect();
+
+    for step in path.windows(2) {
+        let ((x, y), (nx, ny)) = (step[0], step[1]);
+        canvas[y][x] = match (nx.cmp(&x), ny.cmp(&y)) {
+            (Ordering::Greater, _) => '>',
+            (Ordering::Less, _) => '<',
+            (_, Ordering::Greater) => 'v',
+            _ => '^',
+        };
+    }
+    if let Some(&(x, y)) = path.last() {
+        canvas[y][x] = 'E';
+    }
+
+    canvas
+        .iter()
+        .map(|row| row.iter().collect::<String>() + "\n")
+        .collect()
+}
+
+pub fn solve(input: &str) -> usize {
+    climb(input).len() - 1
+}
+
+pub fn solve_2(input: &str) -> usize {
+    climb_from_lowest(input).len() - 1
 }
 
 #[cfg(test)]
 mod tests {
     use crate::common::read_file_to_string;
+    use std::collections::HashMap;
     use test::Bencher;
 
     use super::*;
 
-    #[test]
-    fn it_works_simple() {
-        let simple = r"Sabqponm
+    const EXAMPLE: &str = r"Sabqponm
 abcryxxl
 accszExk
 acctuvwj
 abdefghi";
+
+    #[test]
+    fn it_works_simple() {
+        let simple = EXAMPLE;
         let p1 = solve(&simple);
 
         assert_eq!(p1, 31);
@@ -191,11 +384,7 @@ abdefghi";
 
     #[test]
     fn it_works_simple_2() {
-        let simple = r"Sabqponm
-abcryxxl
-accszExk
-acctuvwj
-abdefghi";
+        let simple = EXAMPLE;
         let res = solve_2(simple);
 
         assert_eq!(res, 29);
@@ -208,4 +397,220 @@ abdefghi";
 
         assert_eq!(res, 321);
     }
+
+    fn assert_valid_route(input: &str, path: &[Position]) {
+        let (grid, _, _) = parse(input);
+
+        for step in path.windows(2) {
+            let ((x, y), (nx, ny)) = (step[0], step[1]);
+            assert_eq!(x.abs_diff(nx) + y.abs_diff(ny), 1);
+            assert!(grid[ny][nx] <= grid[y][x] + 1);
+        }
+    }
+
+    #[test]
+    fn renders_route() {
+        let path = climb(EXAMPLE);
+
+        assert_valid_route(EXAMPLE, &path);
+        assert_eq!(path.first(), Some(&(0, 0)));
+        assert_eq!(path.last(), Some(&(5, 2)));
+        assert_eq!(
+            render_path(EXAMPLE, &path),
+            ">>vv<<<<\n..vvv<<^\n..vv>E^^\n..v>>>^^\n..>>>>>^\n"
+        );
+    }
+
+    #[test]
+    fn renders_route_from_lowest() {
+        let lines = read_file_to_string("src/day12/input");
+        let path = climb_from_lowest(lines.trim_end());
+
+        assert_valid_route(lines.trim_end(), &path);
+        assert_eq!(path.len(), 322);
+        assert_eq!(render_path(lines.trim_end(), &path).matches('E').count(), 1);
+    }
+
+    #[test]
+    fn weighted_search_agrees_with_bfs() {
+        let lines = read_file_to_string("src/day12/input");
+        let (cost, path) = cheapest_route(lines.trim_end(), &ClimbingRules::STANDARD).unwrap();
+
+        assert_eq!(cost, 330);
+        assert_eq!(path.len(), 331);
+    }
+
+    // The search as it was before the flat arrays, kept to benchmark against
+    fn hashmap_shortest_path<F>(
+        adj_list: &[Vec<usize>],
+        start: Position,
+        is_goal: F,
+    ) -> Option<Vec<Position>>
+    where
+        F: Fn(&Position) -> bool,
+    {
+        let mut dist: HashMap<Position, u32> = adj_list
+            .iter()
+            .enumerate()
+            .flat_map(|(y, row)| (0..row.len()).map(move |x| ((x, y), u32::MAX)))
+            .collect();
+        let mut prev: HashMap<Position, Position> = HashMap::new();
+        let mut heap = BinaryHeap::new();
+
+        dist.insert(start, 0);
+        heap.push(State {
+            position: start,
+            cost: 0,
+        });
+
+        while let Some(State { position, cost }) = heap.pop() {
+            if is_goal(&position) {
+                let mut path: Vec<_> =
+                    iter::successors(Some(position), |p| prev.get(p).copied()).collect();
+                path.reverse();
+                return Some(path);
+            }
+
+            if cost > dist[&position] {
+                continue;
+            }
+
+            for pos in get_neighs(adj_list, position, &ClimbingRules::STANDARD, true) {
+                let next_cost = cost + 1;
+
+                if next_cost < dist[&pos] {
+                    heap.push(State {
+                        position: pos,
+                        cost: next_cost,
+                    });
+                    dist.insert(pos, next_cost);
+                    prev.insert(pos, position);
+                }
+            }
+        }
+
+        None
+    }
+
+    #[bench]
+    fn bench_part_1_hashmap(b: &mut Bencher) {
+        let lines = read_file_to_string("src/day12/input");
+        b.iter(|| {
+            let (grid, goal, start) = parse(lines.trim_end());
+            let path = hashmap_shortest_path(&grid, start, |pos| pos == &goal).unwrap();
+            assert_eq!(path.len() - 1, 330);
+        })
+    }
+
+    #[bench]
+    fn bench_part_2_hashmap(b: &mut Bencher) {
+        let lines = read_file_to_string("src/day12/input");
+        b.iter(|| {
+            let (grid, _start, goal) = parse(lines.trim_end());
+            let path =
+                hashmap_shortest_path(&grid, goal, |&(x, y)| grid[y][x] == 'a' as usize).unwrap();
+            assert_eq!(path.len() - 1, 321);
+        })
+    }
+
+    #[bench]
+    fn bench_part_1_dijkstra(b: &mut Bencher) {
+        let lines = read_file_to_string("src/day12/input");
+        b.iter(|| {
+            let (p1, _) = cheapest_route(lines.trim_end(), &ClimbingRules::STANDARD).unwrap();
+            assert_eq!(p1, 330);
+        })
+    }
+
+    #[bench]
+    fn bench_part_2_dijkstra(b: &mut Bencher) {
+        let lines = read_file_to_string("src/day12/input");
+        b.iter(|| {
+            let (grid, _start, goal) = parse(lines.trim_end());
+            let rules = ClimbingRules {
+                max_ascent: usize::MAX,
+                max_descent: 1,
+                ..ClimbingRules::STANDARD
+            };
+            let (res, _) =
+                cheapest_path(&grid, goal, |&(x, y)| grid[y][x] == 'a' as usize, &rules).unwrap();
+            assert_eq!(res, 321);
+        })
+    }
+
+    #[test]
+    fn unlimited_ascent_goes_straight() {
+        let rules = ClimbingRules {
+            max_ascent: usize::MAX,
+            ..ClimbingRules::STANDARD
+        };
+        let (cost, path) = cheapest_route(EXAMPLE, &rules).unwrap();
+
+        assert_eq!(cost, 7);
+        assert_eq!(path.len(), 8);
+    }
+
+    #[test]
+    fn no_descents_allowed() {
+        let rules = ClimbingRules {
+            max_descent: 0,
+            ..ClimbingRules::STANDARD
+        };
+        let (grid, _, _) = parse(EXAMPLE);
+        let (_, path) = cheapest_route(EXAMPLE, &rules).unwrap();
+
+        assert_valid_route(EXAMPLE, &path);
+        for step in path.windows(2) {
+            let ((x, y), (nx, ny)) = (step[0], step[1]);
+            assert!(grid[ny][nx] >= grid[y][x]);
+        }
+
+        let rules = ClimbingRules {
+            max_ascent: 0,
+            ..ClimbingRules::STANDARD
+        };
+        assert_eq!(cheapest_route(EXAMPLE, &rules), None);
+    }
+
+    #[test]
+    fn climbing_costs_more_than_descending() {
+        let rules = ClimbingRules {
+            step_cost: |dh| 1 + 2 * dh.max(0) as u32,
+            ..ClimbingRules::STANDARD
+        };
+        let lines = read_file_to_string("src/day12/input");
+        let (grid, _, _) = parse(lines.trim_end());
+        let (cost, path) = cheapest_route(lines.trim_end(), &rules).unwrap();
+
+        let cost_of = |path: &[Position]| -> usize {
+            path.windows(2)
+                .map(|step| {
+                    let ((x, y), (nx, ny)) = (step[0], step[1]);
+                    rules.cost(grid[y][x], grid[ny][nx]) as usize
+                })
+                .sum()
+        };
+
+        assert_valid_route(lines.trim_end(), &path);
+        assert_eq!(cost, cost_of(&path));
+        assert!(cost <= cost_of(&climb(lines.trim_end())));
+        assert_eq!(cost, 384);
+    }
+
+    #[test]
+    fn routes_too_costly_to_track_are_unreachable() {
+        let rules = ClimbingRules {
+            max_ascent: usize::MAX,
+            step_cost: |_| u32::MAX / 3,
+            ..ClimbingRules::STANDARD
+        };
+
+        assert_eq!(
+            cheapest_route("SbE", &rules),
+            Some((2 * (u32::MAX / 3) as usize, vec![(0, 0), (1, 0), (2, 0)]))
+        );
+        // Exactly u32::MAX, which is reserved for unvisited cells
+        assert_eq!(cheapest_route("SbcE", &rules), None);
+        assert_eq!(cheapest_route("SbcdE", &rules), None);
+    }
 }
@@ -1,20 +1,23 @@
-use crate::day13::Package::{List, Num};
+use crate::day13::Packet::{List, Num};
 use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::fmt;
 use std::iter;
+use std::str::FromStr;
 
-#[derive(Debug, Eq, PartialEq)]
-enum Package {
-    List(Vec<Package>),
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Packet {
+    List(Vec<Packet>),
     Num(u32),
 }
 
-impl PartialOrd<Self> for Package {
+impl PartialOrd<Self> for Packet {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
         Some(self.cmp(other))
     }
 }
 
-impl Ord for Package {
+impl Ord for Packet {
     fn cmp(&self, other: &Self) -> Ordering {
         match (self, other) {
             (List(v1), List(v2)) => is_vec_in_right_order(v1, v2),
@@ -25,66 +28,215 @@ impl Ord for Package {
     }
 }
 
-fn parse_line(input: &str) -> (Package, usize) {
-    let mut vec = vec![];
+impl fmt::Display for Packet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Num(n) => write!(f, "{}", n),
+            List(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "]")
+            }
+        }
+    }
+}
 
-    let mut start_num_index = None;
+#[derive(Debug, Eq, PartialEq)]
+pub enum ParseError {
+    UnexpectedEnd,
+    UnexpectedChar { position: usize, found: char },
+    NumberTooLarge { position: usize },
+    TrailingInput { position: usize },
+}
 
-    let str_len = input.len();
-    let mut index = 0;
-    while index < str_len {
-        match &input[index..index + 1] {
-            "[" => {
-                let (pkg, num_consumed) = parse_line(&input[index + 1..]);
-                index += num_consumed;
-                vec.push(pkg);
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::UnexpectedEnd => write!(f, "unexpected end of packet"),
+            ParseError::UnexpectedChar { position, found } => {
+                write!(f, "unexpected {:?} at {}", found, position)
             }
-            "]" => {
-                if let Some(start_index) = start_num_index.take() {
-                    let num = parse_num_from_slice(input, start_index, index);
-                    vec.push(Num(num));
-                }
-                return (List(vec), index + 1);
+            ParseError::NumberTooLarge { position } => {
+                write!(f, "number at {} does not fit in a u32", position)
             }
-            "," => {
-                if let Some(start_index) = start_num_index.take() {
-                    let num = parse_num_from_slice(input, start_index, index);
-                    vec.push(Num(num));
-                }
+            ParseError::TrailingInput { position } => {
+                write!(f, "trailing input after packet at {}", position)
             }
-            _ => {
-                if start_num_index.is_none() {
-                    start_num_index = Some(index);
+        }
+    }
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    position: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<char> {
+        self.input[self.position..].chars().next()
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), ParseError> {
+        match self.peek() {
+            Some(c) if c == expected => {
+                self.position += 1;
+                Ok(())
+            }
+            Some(found) => Err(ParseError::UnexpectedChar {
+                position: self.position,
+                found,
+            }),
+            None => Err(ParseError::UnexpectedEnd),
+        }
+    }
+
+    // packet := list | number
+    fn packet(&mut self) -> Result<Packet, ParseError> {
+        match self.peek() {
+            Some('[') => self.list(),
+            Some(c) if c.is_ascii_digit() => self.number(),
+            Some(found) => Err(ParseError::UnexpectedChar {
+                position: self.position,
+                found,
+            }),
+            None => Err(ParseError::UnexpectedEnd),
+        }
+    }
+
+    // list := '[' (packet (',' packet)*)? ']'
+    fn list(&mut self) -> Result<Packet, ParseError> {
+        self.expect('[')?;
+
+        let mut items = vec![];
+        if self.peek() == Some(']') {
+            self.position += 1;
+            return Ok(List(items));
+        }
+
+        loop {
+            items.push(self.packet()?);
+
+            match self.peek() {
+                Some(',') => self.position += 1,
+                Some(']') => {
+                    self.position += 1;
+                    return Ok(List(items));
                 }
+                Some(found) => {
+                    return Err(ParseError::UnexpectedChar {
+                        position: self.position,
+                        found,
+                    })
+                }
+                None => return Err(ParseError::UnexpectedEnd),
             }
         }
-        index += 1;
     }
 
-    let ret = if vec.len() == 1 {
-        vec.remove(0)
-    } else {
-        List(vec)
-    };
+    // number := digit+
+    fn number(&mut self) -> Result<Packet, ParseError> {
+        let start = self.position;
+        let len = self.input[start..]
+            .bytes()
+            .take_while(u8::is_ascii_digit)
+            .count();
+        self.position += len;
+
+        self.input[start..self.position]
+            .parse()
+            .map(Num)
+            .map_err(|_| ParseError::NumberTooLarge { position: start })
+    }
+}
 
-    (ret, str_len)
+impl FromStr for Packet {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser {
+            input: s,
+            position: 0,
+        };
+        let packet = parser.packet()?;
+
+        if parser.position < s.len() {
+            return Err(ParseError::TrailingInput {
+                position: parser.position,
+            });
+        }
+
+        Ok(packet)
+    }
 }
 
-fn parse_num_from_slice(input: &str, start_index: usize, index: usize) -> u32 {
-    input[start_index..index].parse().unwrap()
+// A generic JSON document, for handing packets to and from other tools
+#[derive(Clone, Debug, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(BTreeMap<String, Json>),
 }
 
-fn parse(input: &str) -> (Package, Package) {
-    let (p1, p2) = input.split_once('\n').unwrap();
+impl From<&Packet> for Json {
+    fn from(packet: &Packet) -> Self {
+        match packet {
+            Num(n) => Json::Number(*n as f64),
+            List(items) => Json::Array(items.iter().map(Json::from).collect()),
+        }
+    }
+}
+
+impl From<Packet> for Json {
+    fn from(packet: Packet) -> Self {
+        Json::from(&packet)
+    }
+}
 
-    let (vec1, _) = parse_line(p1);
+// Packets only hold arrays and non-negative integers that fit in a u32
+#[derive(Debug, PartialEq)]
+pub struct NotAPacket(pub Json);
 
-    let (vec2, _) = parse_line(p2);
+impl TryFrom<&Json> for Packet {
+    type Error = NotAPacket;
 
-    (vec1, vec2)
+    fn try_from(json: &Json) -> Result<Self, Self::Error> {
+        match json {
+            Json::Number(n) if n.fract() == 0.0 && *n >= 0.0 && *n <= u32::MAX as f64 => {
+                Ok(Num(*n as u32))
+            }
+            Json::Array(items) => items
+                .iter()
+                .map(Packet::try_from)
+                .collect::<Result<_, _>>()
+                .map(List),
+            other => Err(NotAPacket(other.clone())),
+        }
+    }
 }
 
-fn is_vec_in_right_order(v1: &Vec<Package>, v2: &Vec<Package>) -> Ordering {
+impl TryFrom<Json> for Packet {
+    type Error = NotAPacket;
+
+    fn try_from(json: Json) -> Result<Self, Self::Error> {
+        Packet::try_from(&json)
+    }
+}
+
+fn parse(input: &str) -> (Packet, Packet) {
+    let (p1, p2) = input.split_once('\n').unwrap();
+
+    (p1.parse().unwrap(), p2.parse().unwrap())
+}
+
+fn is_vec_in_right_order(v1: &Vec<Packet>, v2: &Vec<Packet>) -> Ordering {
     let max_len = (v1.len()).max(v2.len());
     for i in 0..max_len {
         let ordering = match (v1.get(i), v2.get(i)) {
@@ -101,6 +253,183 @@ fn is_vec_in_right_order(v1: &Vec<Package>, v2: &Vec<Package>) -> Ordering {
     return Ordering::Equal;
 }
 
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Side {
+    Left,
+    Right,
+}
+
+impl fmt::Display for Side {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Side::Left => write!(f, "left"),
+            Side::Right => write!(f, "right"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum StepKind {
+    Compare(Packet, Packet),
+    // An integer on this side was wrapped in a list to match the other side
+    Promote(Side, u32),
+    // The deciding comparisons
+    Smaller(Side),
+    RanOut(Side),
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Step {
+    pub depth: usize,
+    // Indices into the outer lists leading to the values being compared
+    pub path: Vec<usize>,
+    pub kind: StepKind,
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:indent$}- ", "", indent = 2 * self.depth)?;
+
+        let (side, event) = match &self.kind {
+            StepKind::Compare(left, right) => return write!(f, "Compare {} vs {}", left, right),
+            StepKind::Promote(side, n) => {
+                return write!(
+                    f,
+                    "Mixed types; convert {} to [{}] and retry comparison",
+                    side, n
+                )
+            }
+            StepKind::Smaller(side) => (side, "side is smaller"),
+            StepKind::RanOut(side) => (side, "side ran out of items"),
+        };
+
+        // Whichever side is smaller or runs out first comes first
+        let verdict = match side {
+            Side::Left => "in the right order",
+            Side::Right => "not in the right order",
+        };
+        let side = match side {
+            Side::Left => "Left",
+            Side::Right => "Right",
+        };
+
+        write!(f, "{} {}, so inputs are {}", side, event, verdict)
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Explanation {
+    pub ordering: Ordering,
+    pub steps: Vec<Step>,
+}
+
+impl Explanation {
+    // The step that settled the ordering, if the packets weren't equal
+    pub fn deciding_step(&self) -> Option<&Step> {
+        self.steps
+            .last()
+            .filter(|step| matches!(step.kind, StepKind::Smaller(_) | StepKind::RanOut(_)))
+    }
+}
+
+impl fmt::Display for Explanation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for step in &self.steps {
+            writeln!(f, "{}", step)?;
+        }
+        Ok(())
+    }
+}
+
+// Compares two packets the same way `Ord` does, recording every step along the way
+pub fn explain(left: &Packet, right: &Packet) -> Explanation {
+    let mut steps = vec![];
+    let ordering = explain_into(left, right, 0, &mut vec![], &mut steps);
+
+    Explanation { ordering, steps }
+}
+
+fn explain_into(
+    left: &Packet,
+    right: &Packet,
+    depth: usize,
+    path: &mut Vec<usize>,
+    steps: &mut Vec<Step>,
+) -> Ordering {
+    fn record(steps: &mut Vec<Step>, depth: usize, path: &[usize], kind: StepKind) {
+        steps.push(Step {
+            depth,
+            path: path.to_vec(),
+            kind,
+        })
+    }
+
+    record(
+        steps,
+        depth,
+        path,
+        StepKind::Compare(left.clone(), right.clone()),
+    );
+
+    match (left, right) {
+        (Num(x), Num(y)) => {
+            let ordering = x.cmp(y);
+            match ordering {
+                Ordering::Less => record(steps, depth + 1, path, StepKind::Smaller(Side::Left)),
+                Ordering::Greater => record(steps, depth + 1, path, StepKind::Smaller(Side::Right)),
+                Ordering::Equal => {}
+            }
+            ordering
+        }
+        (Num(x), List(_)) => {
+            record(steps, depth + 1, path, StepKind::Promote(Side::Left, *x));
+            explain_into(&List(vec![Num(*x)]), right, depth + 1, path, steps)
+        }
+        (List(_), Num(y)) => {
+            record(steps, depth + 1, path, StepKind::Promote(Side::Right, *y));
+            explain_into(left, &List(vec![Num(*y)]), depth + 1, path, steps)
+        }
+        (List(v1), List(v2)) => {
+            for i in 0..v1.len().max(v2.len()) {
+                let ordering = match (v1.get(i), v2.get(i)) {
+                    (Some(x), Some(y)) => {
+                        path.push(i);
+                        let ordering = explain_into(x, y, depth + 1, path, steps);
+                        path.pop();
+                        ordering
+                    }
+                    (Some(_), None) => {
+                        record(steps, depth + 1, path, StepKind::RanOut(Side::Right));
+                        Ordering::Greater
+                    }
+                    _ => {
+                        record(steps, depth + 1, path, StepKind::RanOut(Side::Left));
+                        Ordering::Less
+                    }
+                };
+
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+            Ordering::Equal
+        }
+    }
+}
+
+// The puzzle's walkthrough for every pair in the input
+pub fn walkthrough(input: &str) -> String {
+    input
+        .split("\n\n")
+        .enumerate()
+        .map(|(index, pairs)| {
+            let (p1, p2) = parse(pairs);
+            format!("== Pair {} ==\n{}", index + 1, explain(&p1, &p2))
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
 pub fn solve(input: &str) -> usize {
     input
         .split("\n\n")
@@ -125,8 +454,8 @@ pub fn solve_2(input: &str) -> usize {
 
             iter::once(p1).chain(iter::once(p2))
         })
-        .chain(iter::once(List(vec![Num(2)])))
-        .chain(iter::once(List(vec![Num(6)])))
+        .chain(iter::once(List(vec![List(vec![Num(2)])])))
+        .chain(iter::once(List(vec![List(vec![Num(6)])])))
         .collect();
 
     packages.sort();
@@ -135,7 +464,7 @@ pub fn solve_2(input: &str) -> usize {
         .iter()
         .enumerate()
         .filter_map(|(i, p)| {
-            if p == &List(vec![Num(2)]) || p == &List(vec![Num(6)]) {
+            if p == &List(vec![List(vec![Num(2)])]) || p == &List(vec![List(vec![Num(6)])]) {
                 Some(i + 1)
             } else {
                 None
@@ -151,6 +480,30 @@ mod tests {
 
     use super::*;
 
+    const EXAMPLE: &str = r"[1,1,3,1,1]
+[1,1,5,1,1]
+
+[[1],[2,3,4]]
+[[1],4]
+
+[9]
+[[8,7,6]]
+
+[[4,4],4,4]
+[[4,4],4,4,4]
+
+[7,7,7,7]
+[7,7,7]
+
+[]
+[3]
+
+[[[]]]
+[[]]
+
+[1,[2,[3,[4,[5,6,7]]]],8,9]
+[1,[2,[3,[4,[5,6,0]]]],8,9]";
+
     #[test]
     fn it_handles_pair_1_example() {
         let res = solve(&"[1,1,3,1,1]\n[1,1,5,1,1]");
@@ -198,29 +551,7 @@ mod tests {
 
     #[test]
     fn it_works_simple() {
-        let simple = r"[1,1,3,1,1]
-[1,1,5,1,1]
-
-[[1],[2,3,4]]
-[[1],4]
-
-[9]
-[[8,7,6]]
-
-[[4,4],4,4]
-[[4,4],4,4,4]
-
-[7,7,7,7]
-[7,7,7]
-
-[]
-[3]
-
-[[[]]]
-[[]]
-
-[1,[2,[3,[4,[5,6,7]]]],8,9]
-[1,[2,[3,[4,[5,6,0]]]],8,9]";
+        let simple = EXAMPLE;
         let p1 = solve(&simple);
 
         assert_eq!(p1, 13);
@@ -254,40 +585,191 @@ mod tests {
 
     #[test]
     fn it_works_simple_2() {
-        let simple = r"[1,1,3,1,1]
-[1,1,5,1,1]
+        let simple = EXAMPLE;
 
-[[1],[2,3,4]]
-[[1],4]
+        let res = solve_2(simple);
 
-[9]
-[[8,7,6]]
+        assert_eq!(res, 140);
+    }
 
-[[4,4],4,4]
-[[4,4],4,4,4]
+    #[test]
+    fn it_works_2() {
+        let lines = read_file_to_string("src/day13/input");
+        let res = solve_2(&lines.trim_end());
 
-[7,7,7,7]
-[7,7,7]
+        assert_eq!(res, 22288);
+    }
 
-[]
-[3]
+    #[test]
+    fn display_round_trips() {
+        let lines = read_file_to_string("src/day13/input");
 
-[[[]]]
-[[]]
+        for line in lines.lines().filter(|line| !line.is_empty()) {
+            let packet: Packet = line.parse().unwrap();
+            assert_eq!(packet.to_string(), line);
+        }
+    }
 
-[1,[2,[3,[4,[5,6,7]]]],8,9]
-[1,[2,[3,[4,[5,6,0]]]],8,9]";
+    #[test]
+    fn parses_nested_lists() {
+        assert_eq!("[]".parse(), Ok(List(vec![])));
+        assert_eq!("[[]]".parse(), Ok(List(vec![List(vec![])])));
+        assert_eq!(
+            "[10,[2]]".parse(),
+            Ok(List(vec![Num(10), List(vec![Num(2)])]))
+        );
+    }
 
-        let res = solve_2(simple);
+    #[test]
+    fn reports_parse_errors() {
+        assert_eq!("".parse::<Packet>(), Err(ParseError::UnexpectedEnd));
+        assert_eq!("[1,2".parse::<Packet>(), Err(ParseError::UnexpectedEnd));
+        assert_eq!(
+            "[1,,2]".parse::<Packet>(),
+            Err(ParseError::UnexpectedChar {
+                position: 3,
+                found: ','
+            })
+        );
+        assert_eq!(
+            "[1 2]".parse::<Packet>(),
+            Err(ParseError::UnexpectedChar {
+                position: 2,
+                found: ' '
+            })
+        );
+        assert_eq!(
+            "[1]]".parse::<Packet>(),
+            Err(ParseError::TrailingInput { position: 3 })
+        );
+        assert_eq!(
+            "[99999999999]".parse::<Packet>(),
+            Err(ParseError::NumberTooLarge { position: 1 })
+        );
+    }
 
-        assert_eq!(res, 140);
+    #[test]
+    fn converts_to_and_from_json() {
+        let packet: Packet = "[1,[2,[]]]".parse().unwrap();
+        let json = Json::from(&packet);
+
+        assert_eq!(
+            json,
+            Json::Array(vec![
+                Json::Number(1.0),
+                Json::Array(vec![Json::Number(2.0), Json::Array(vec![])])
+            ])
+        );
+        assert_eq!(Packet::try_from(json), Ok(packet));
+
+        assert_eq!(
+            Packet::try_from(Json::Array(vec![Json::Number(1.5)])),
+            Err(NotAPacket(Json::Number(1.5)))
+        );
+        assert_eq!(
+            Packet::try_from(Json::Array(vec![Json::Null])),
+            Err(NotAPacket(Json::Null))
+        );
     }
 
     #[test]
-    fn it_works_2() {
+    fn explains_example_like_the_puzzle() {
+        let expected = r"== Pair 1 ==
+- Compare [1,1,3,1,1] vs [1,1,5,1,1]
+  - Compare 1 vs 1
+  - Compare 1 vs 1
+  - Compare 3 vs 5
+    - Left side is smaller, so inputs are in the right order
+
+== Pair 2 ==
+- Compare [[1],[2,3,4]] vs [[1],4]
+  - Compare [1] vs [1]
+    - Compare 1 vs 1
+  - Compare [2,3,4] vs 4
+    - Mixed types; convert right to [4] and retry comparison
+    - Compare [2,3,4] vs [4]
+      - Compare 2 vs 4
+        - Left side is smaller, so inputs are in the right order
+
+== Pair 3 ==
+- Compare [9] vs [[8,7,6]]
+  - Compare 9 vs [8,7,6]
+    - Mixed types; convert left to [9] and retry comparison
+    - Compare [9] vs [8,7,6]
+      - Compare 9 vs 8
+        - Right side is smaller, so inputs are not in the right order
+
+== Pair 4 ==
+- Compare [[4,4],4,4] vs [[4,4],4,4,4]
+  - Compare [4,4] vs [4,4]
+    - Compare 4 vs 4
+    - Compare 4 vs 4
+  - Compare 4 vs 4
+  - Compare 4 vs 4
+  - Left side ran out of items, so inputs are in the right order
+
+== Pair 5 ==
+- Compare [7,7,7,7] vs [7,7,7]
+  - Compare 7 vs 7
+  - Compare 7 vs 7
+  - Compare 7 vs 7
+  - Right side ran out of items, so inputs are not in the right order
+
+== Pair 6 ==
+- Compare [] vs [3]
+  - Left side ran out of items, so inputs are in the right order
+
+== Pair 7 ==
+- Compare [[[]]] vs [[]]
+  - Compare [[]] vs []
+    - Right side ran out of items, so inputs are not in the right order
+
+== Pair 8 ==
+- Compare [1,[2,[3,[4,[5,6,7]]]],8,9] vs [1,[2,[3,[4,[5,6,0]]]],8,9]
+  - Compare 1 vs 1
+  - Compare [2,[3,[4,[5,6,7]]]] vs [2,[3,[4,[5,6,0]]]]
+    - Compare 2 vs 2
+    - Compare [3,[4,[5,6,7]]] vs [3,[4,[5,6,0]]]
+      - Compare 3 vs 3
+      - Compare [4,[5,6,7]] vs [4,[5,6,0]]
+        - Compare 4 vs 4
+        - Compare [5,6,7] vs [5,6,0]
+          - Compare 5 vs 5
+          - Compare 6 vs 6
+          - Compare 7 vs 0
+            - Right side is smaller, so inputs are not in the right order
+";
+
+        assert_eq!(walkthrough(EXAMPLE), expected);
+    }
+
+    #[test]
+    fn explains_deciding_step() {
+        let (left, right) = parse("[1,[2,[3,[4,[5,6,7]]]],8,9]\n[1,[2,[3,[4,[5,6,0]]]],8,9]");
+        let explanation = explain(&left, &right);
+
+        assert_eq!(explanation.ordering, Ordering::Greater);
+        assert_eq!(
+            explanation.deciding_step(),
+            Some(&Step {
+                depth: 6,
+                path: vec![1, 1, 1, 1, 2],
+                kind: StepKind::Smaller(Side::Right),
+            })
+        );
+
+        let equal = explain(&left, &left);
+        assert_eq!(equal.ordering, Ordering::Equal);
+        assert_eq!(equal.deciding_step(), None);
+    }
+
+    #[test]
+    fn explanations_agree_with_ord() {
         let lines = read_file_to_string("src/day13/input");
-        let res = solve_2(&lines.trim_end());
 
-        assert_eq!(res, 22288);
+        for pairs in lines.trim_end().split("\n\n") {
+            let (p1, p2) = parse(pairs);
+            assert_eq!(explain(&p1, &p2).ordering, p1.cmp(&p2));
+        }
     }
 }